@@ -1,7 +1,7 @@
 use async_net::Ipv4Addr;
 use bevy::tasks::TaskPool;
 use bevy::{prelude::*, tasks::TaskPoolBuilder};
-use bevy_eventwork::{ConnectionId, EventworkRuntime, Network, NetworkData, NetworkEvent};
+use bevy_eventwork::{EventworkRuntime, Network, NetworkData, NetworkEvent};
 use std::net::{IpAddr, SocketAddr};
 
 use bevy_eventwork::tcp::{NetworkSettings, TcpProvider};
@@ -13,11 +13,12 @@ fn main() {
     app.add_plugins((MinimalPlugins, bevy::log::LogPlugin::default()));
 
     // Before we can register the potential message types, we
-    // need to add the plugin
-    app.add_plugins(bevy_eventwork::EventworkPlugin::<
-        TcpProvider,
-        bevy::tasks::TaskPool,
-    >::default());
+    // need to add the plugin. We also ask it to spawn an entity for every connection,
+    // which will be despawned automatically once the client disconnects.
+    app.add_plugins(
+        bevy_eventwork::EventworkPlugin::<TcpProvider, bevy::tasks::TaskPool>::default()
+            .with_connection_entities(),
+    );
 
     // Make sure you insert the EventworkRuntime resource with your chosen Runtime
     app.insert_resource(EventworkRuntime(
@@ -66,7 +67,7 @@ fn setup_networking(
 }
 
 #[derive(Component)]
-struct Player(ConnectionId);
+struct Player;
 
 fn handle_connection_events(
     mut commands: Commands,
@@ -75,7 +76,9 @@ fn handle_connection_events(
 ) {
     for event in network_events.read() {
         if let NetworkEvent::Connected(conn_id) = event {
-            commands.spawn((Player(*conn_id),));
+            if let Some(entity) = net.connection_entity(*conn_id) {
+                commands.entity(entity).insert(Player);
+            }
 
             // Broadcasting sends the message to all connected players! (Including the just connected one in this case)
             net.broadcast(shared::NewChatMessage {
@@ -0,0 +1,516 @@
+//! # Admission
+//!
+//! A listening [`Network`] accepts every connection by default. With an [`AdmissionPolicy`] resource,
+//! every accepted socket is checked before a connection is made for it:
+//!
+//! - Sockets from a banned address are rejected
+//! - If any addresses are allowed, sockets from other addresses are rejected
+//! - Sockets are rejected while the server is full, or while there are too many connections from the same address
+//!
+//! Rejected sockets are closed right away and reported with a [`NetworkEvent::Error`](crate::NetworkEvent::Error)
+//! containing a [`NetworkError::Rejected`] with the [`RejectReason`].
+//! Connections made with [`Network::connect`](crate::Network::connect) aren't checked,
+//! and the local client of [`Network::connect_local`](crate::Network::connect_local) counts towards no limit.
+//!
+//! The policy can be changed at any time, the changes apply to sockets accepted afterwards.
+//! Addresses are matched as [`IpNet`]s, single addresses or ranges in CIDR notation like `10.0.0.0/8`.
+//! Address rules only apply to providers with [addresses](crate::managers::NetworkProvider::peer_addr).
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkEvent,
+//!     admission::{AdmissionPolicy, IpNet},
+//!     error::NetworkError,
+//!     tcp::TcpProvider,
+//! };
+//!
+//! fn build(app: &mut App) {
+//!     app.insert_resource(
+//!         AdmissionPolicy::<TcpProvider>::default()
+//!             .max_connections(64)
+//!             .max_connections_per_ip(4),
+//!     );
+//!     app.add_systems(Update, (ban_cheater, log_rejections));
+//! }
+//!
+//! fn ban_cheater(keys: Res<ButtonInput<KeyCode>>, mut policy: ResMut<AdmissionPolicy<TcpProvider>>) {
+//!     if keys.just_pressed(KeyCode::KeyB) {
+//!         policy.ban("203.0.113.0/24".parse::<IpNet>().expect("Valid range"));
+//!     }
+//! }
+//!
+//! fn log_rejections(mut network_events: EventReader<NetworkEvent>) {
+//!     for event in network_events.read() {
+//!         if let NetworkEvent::Error(NetworkError::Rejected(peer_addr, reason)) = event {
+//!             info!("Rejected {:?}: {}", peer_addr, reason);
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+};
+
+use bevy::prelude::Resource;
+
+use crate::{
+    error::NetworkError,
+    managers::{Network, NetworkProvider},
+};
+
+/// An IP address or a range of them, like `192.168.0.0/16` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// The range of addresses starting with the first `prefix_len` bits of the address
+    ///
+    /// Ranges of Ipv4-mapped Ipv6 addresses, like `::ffff:10.0.0.0/104`, become the Ipv4 range, `10.0.0.0/8`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, NetworkError> {
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_len {
+            return Err(NetworkError::Error(format!(
+                "Invalid prefix length for {}: {}",
+                addr, prefix_len
+            )));
+        }
+
+        // Ipv4 clients of a dual stack socket show up as mapped Ipv6 addresses, which are matched as Ipv4
+        let (addr, prefix_len) = match addr {
+            IpAddr::V6(v6) if prefix_len >= 96 => match v6.to_ipv4_mapped() {
+                Some(v4) => (IpAddr::V4(v4), prefix_len - 96),
+                None => (addr, prefix_len),
+            },
+            _ => (addr, prefix_len),
+        };
+
+        // Keep only the prefix, so equal ranges compare equal
+        let addr = match addr {
+            IpAddr::V4(v4) => IpAddr::V4(Ipv4Addr::from(u32::from(v4) & mask_v4(prefix_len))),
+            IpAddr::V6(v6) => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & mask_v6(prefix_len))),
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// The first address of the range
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The amount of leading bits all addresses of the range share
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns true if the address is part of the range
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // Ipv4 clients of a dual stack socket show up as mapped Ipv6 addresses
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & mask_v4(self.prefix_len) == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & mask_v6(self.prefix_len) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A mask of the first `prefix_len` bits of an Ipv4 address
+fn mask_v4(prefix_len: u8) -> u32 {
+    u32::MAX
+        .checked_shl(32 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+/// A mask of the first `prefix_len` bits of an Ipv6 address
+fn mask_v6(prefix_len: u8) -> u128 {
+    u128::MAX
+        .checked_shl(128 - u32::from(prefix_len))
+        .unwrap_or(0)
+}
+
+impl From<IpAddr> for IpNet {
+    /// The range of just this address
+    fn from(addr: IpAddr) -> Self {
+        let addr = addr.to_canonical();
+        let prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        Self { addr, prefix_len }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = NetworkError;
+
+    /// Parses a single address like `10.1.2.3`, or a range like `10.0.0.0/8`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NetworkError::Error(format!("Invalid IP address or range: {}", s));
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => Self::new(
+                addr.parse().map_err(|_| invalid())?,
+                prefix_len.parse().map_err(|_| invalid())?,
+            ),
+            None => Ok(Self::from(s.parse::<IpAddr>().map_err(|_| invalid())?)),
+        }
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Why a socket was rejected, see [`NetworkError::Rejected`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectReason {
+    /// The address is banned
+    Banned,
+    /// Only other addresses are allowed
+    NotAllowed,
+    /// The server has as many connections as it allows
+    ServerFull,
+    /// There are as many connections from the address as the server allows
+    TooManyFromIp,
+}
+
+impl Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Banned => f.write_str("Banned"),
+            Self::NotAllowed => f.write_str("Not allowed"),
+            Self::ServerFull => f.write_str("Server full"),
+            Self::TooManyFromIp => f.write_str("Too many connections from this address"),
+        }
+    }
+}
+
+/// Decides which accepted sockets of a [`Network`] become connections, see the [module documentation](self)
+#[derive(Resource)]
+pub struct AdmissionPolicy<NP: NetworkProvider> {
+    max_connections: Option<usize>,
+    max_connections_per_ip: Option<usize>,
+    banned: HashSet<IpNet>,
+    allowed: HashSet<IpNet>,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for AdmissionPolicy<NP> {
+    /// Admit everyone
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_connections_per_ip: None,
+            banned: HashSet::new(),
+            allowed: HashSet::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> AdmissionPolicy<NP> {
+    /// Reject sockets while the server has this many connections, including those that are still authenticating
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.set_max_connections(Some(max_connections));
+        self
+    }
+
+    /// Reject sockets while the server has this many connections from the same address
+    pub fn max_connections_per_ip(mut self, max_connections: usize) -> Self {
+        self.set_max_connections_per_ip(Some(max_connections));
+        self
+    }
+
+    /// Change the amount of connections the server allows, [`None`] for no limit
+    pub fn set_max_connections(&mut self, max_connections: Option<usize>) {
+        self.max_connections = max_connections;
+    }
+
+    /// Change the amount of connections the server allows from the same address, [`None`] for no limit
+    pub fn set_max_connections_per_ip(&mut self, max_connections: Option<usize>) {
+        self.max_connections_per_ip = max_connections;
+    }
+
+    /// Reject sockets from the addresses, returns false if they already were banned
+    ///
+    /// Established connections stay connected, use [`Network::disconnect`] to drop them.
+    pub fn ban(&mut self, net: impl Into<IpNet>) -> bool {
+        self.banned.insert(net.into())
+    }
+
+    /// Lift a ban, returns false if the addresses weren't banned
+    pub fn unban(&mut self, net: impl Into<IpNet>) -> bool {
+        self.banned.remove(&net.into())
+    }
+
+    /// The banned addresses
+    pub fn banned(&self) -> impl Iterator<Item = &IpNet> + '_ {
+        self.banned.iter()
+    }
+
+    /// Allow sockets from the addresses, once any are allowed all other addresses are rejected.
+    /// Returns false if they already were allowed
+    pub fn allow(&mut self, net: impl Into<IpNet>) -> bool {
+        self.allowed.insert(net.into())
+    }
+
+    /// Stop allowing the addresses, returns false if they weren't allowed
+    pub fn disallow(&mut self, net: impl Into<IpNet>) -> bool {
+        self.allowed.remove(&net.into())
+    }
+
+    /// The allowed addresses, everyone who isn't banned is allowed if this is empty
+    pub fn allowed(&self) -> impl Iterator<Item = &IpNet> + '_ {
+        self.allowed.iter()
+    }
+
+    /// Returns true if sockets from the address are neither banned nor left out by the allowed addresses
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        !self.banned.iter().any(|net| net.contains(ip))
+            && (self.allowed.is_empty() || self.allowed.iter().any(|net| net.contains(ip)))
+    }
+
+    /// Checks a newly accepted socket against the policy and the current connections of the network
+    pub(crate) fn admit(
+        &self,
+        net: &Network<NP>,
+        peer_addr: Option<SocketAddr>,
+    ) -> Result<(), RejectReason> {
+        let ip = peer_addr.map(|peer_addr| peer_addr.ip());
+        if let Some(ip) = ip {
+            if self.banned.iter().any(|net| net.contains(ip)) {
+                return Err(RejectReason::Banned);
+            }
+            if !self.is_allowed(ip) {
+                return Err(RejectReason::NotAllowed);
+            }
+        }
+
+        if self
+            .max_connections
+            .is_some_and(|max| net.remote_connection_count() >= max)
+        {
+            return Err(RejectReason::ServerFull);
+        }
+
+        if let (Some(max), Some(ip)) = (self.max_connections_per_ip, ip) {
+            if net.connection_count_from(ip) >= max {
+                return Err(RejectReason::TooManyFromIp);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn net(s: &str) -> IpNet {
+        s.parse().expect("Valid range")
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().expect("Valid address")
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        assert_eq!(
+            net("10.1.2.3"),
+            IpNet::new(ip("10.1.2.3"), 32).expect("Valid range")
+        );
+        assert_eq!(net("10.1.2.3/8").to_string(), "10.0.0.0/8");
+        assert_eq!(net("2001:db8::1/32").to_string(), "2001:db8::/32");
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("2001:db8::/129".parse::<IpNet>().is_err());
+        assert!("10.0.0/8".parse::<IpNet>().is_err());
+        assert!("10.0.0.0/".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn canonicalizes_mapped_ranges() {
+        assert_eq!(net("::ffff:10.0.0.0/104"), net("10.0.0.0/8"));
+        assert_eq!(net("::ffff:10.1.2.3"), net("10.1.2.3"));
+        assert!(net("::ffff:10.0.0.0/104").contains(ip("10.20.30.40")));
+        assert!(net("::ffff:10.0.0.0/104").contains(ip("::ffff:10.20.30.40")));
+    }
+
+    #[test]
+    fn contains_the_range() {
+        let v4 = net("192.168.0.0/16");
+        assert!(v4.contains(ip("192.168.1.1")));
+        assert!(v4.contains(ip("::ffff:192.168.1.1")));
+        assert!(!v4.contains(ip("192.169.0.0")));
+        assert!(!v4.contains(ip("2001:db8::1")));
+        assert!(net("0.0.0.0/0").contains(ip("203.0.113.7")));
+
+        let v6 = net("2001:db8::/32");
+        assert!(v6.contains(ip("2001:db8:1::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+        assert!(!v6.contains(ip("192.168.1.1")));
+    }
+}
+
+#[cfg(all(test, feature = "tcp"))]
+mod loopback_tests {
+    use std::{
+        io::{ErrorKind, Read},
+        net::{TcpListener, TcpStream},
+        time::Duration,
+    };
+
+    use bevy::{
+        ecs::event::{Events, ManualEventReader},
+        prelude::{App, Mut},
+    };
+
+    use super::*;
+    use crate::{
+        tcp::{NetworkSettings, TcpProvider},
+        ConnectionId, EventworkPlugin, EventworkRuntime, NetworkEvent, SimulatedRuntime,
+    };
+
+    /// A server listening on a free loopback port
+    struct Server {
+        app: App,
+        runtime: SimulatedRuntime,
+        addr: SocketAddr,
+        reader: ManualEventReader<NetworkEvent>,
+    }
+
+    impl Server {
+        fn new(policy: AdmissionPolicy<TcpProvider>) -> Self {
+            let runtime = SimulatedRuntime::new();
+            let mut app = App::new();
+            app.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+            app.insert_resource(NetworkSettings::default());
+            app.insert_resource(EventworkRuntime(runtime.clone()));
+            app.insert_resource(policy);
+
+            let addr = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("No free port");
+            app.world_mut()
+                .resource_scope(|world, mut net: Mut<Network<TcpProvider>>| {
+                    net.listen(addr, &runtime, world.resource::<NetworkSettings>())
+                })
+                .expect("Failed to listen");
+            // Binds the listener
+            runtime.run_until_stalled();
+
+            Self {
+                app,
+                runtime,
+                addr,
+                reader: ManualEventReader::default(),
+            }
+        }
+
+        /// Connects a client, and returns it with the connection or the reason it was rejected for
+        fn connect(&mut self) -> (TcpStream, Result<ConnectionId, RejectReason>) {
+            let client = TcpStream::connect(self.addr).expect("Failed to connect");
+            for _ in 0..200 {
+                self.runtime.run_until_stalled();
+                self.app.update();
+                let events = self.app.world().resource::<Events<NetworkEvent>>();
+                let admission = self.reader.read(events).find_map(|event| match event {
+                    NetworkEvent::Connected(conn_id) => Some(Ok(*conn_id)),
+                    NetworkEvent::Error(NetworkError::Rejected(_, reason)) => Some(Err(*reason)),
+                    _ => None,
+                });
+                if let Some(admission) = admission {
+                    return (client, admission);
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("The connection was neither established nor rejected");
+        }
+
+        fn policy(&mut self) -> Mut<'_, AdmissionPolicy<TcpProvider>> {
+            self.app
+                .world_mut()
+                .resource_mut::<AdmissionPolicy<TcpProvider>>()
+        }
+    }
+
+    /// Returns true if the server closed the socket
+    fn is_closed(mut client: TcpStream) -> bool {
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("Invalid timeout");
+        match client.read(&mut [0; 1]) {
+            Ok(read) => read == 0,
+            Err(err) => !matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut),
+        }
+    }
+
+    #[test]
+    fn rejects_sockets_while_full() {
+        let mut server = Server::new(AdmissionPolicy::default().max_connections(1));
+        let (_first, admission) = server.connect();
+        assert!(admission.is_ok());
+
+        let (second, admission) = server.connect();
+        assert_eq!(admission, Err(RejectReason::ServerFull));
+        assert!(is_closed(second));
+    }
+
+    #[test]
+    fn limits_connections_per_ip() {
+        let mut server = Server::new(AdmissionPolicy::default().max_connections_per_ip(2));
+        let (_first, admission) = server.connect();
+        assert!(admission.is_ok());
+        let (_second, admission) = server.connect();
+        assert!(admission.is_ok());
+
+        let (third, admission) = server.connect();
+        assert_eq!(admission, Err(RejectReason::TooManyFromIp));
+        assert!(is_closed(third));
+    }
+
+    #[test]
+    fn applies_bans_and_allowed_ranges_at_runtime() {
+        let mut server = Server::new(AdmissionPolicy::default());
+        server
+            .policy()
+            .ban("127.0.0.0/8".parse::<IpNet>().expect("Valid range"));
+        let (banned, admission) = server.connect();
+        assert_eq!(admission, Err(RejectReason::Banned));
+        assert!(is_closed(banned));
+
+        server
+            .policy()
+            .unban("127.0.0.0/8".parse::<IpNet>().expect("Valid range"));
+        server
+            .policy()
+            .allow("10.0.0.0/8".parse::<IpNet>().expect("Valid range"));
+        let (_not_allowed, admission) = server.connect();
+        assert_eq!(admission, Err(RejectReason::NotAllowed));
+
+        server
+            .policy()
+            .allow("127.0.0.0/24".parse::<IpNet>().expect("Valid range"));
+        let (_allowed, admission) = server.connect();
+        assert!(admission.is_ok());
+    }
+}
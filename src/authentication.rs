@@ -0,0 +1,181 @@
+//! # Authentication
+//!
+//! By default every new connection is established right away. With [`AppNetworkAuthentication::authenticate_connections`],
+//! a new connection stays pending while an authenticator exchanges credentials over it through an [`AuthSession`].
+//! Only once the authenticator succeeds the [`NetworkEvent::Connected`](crate::NetworkEvent::Connected) is sent and
+//! messages of the connection are delivered as [`NetworkData`](crate::NetworkData).
+//!
+//! If the authenticator fails, doesn't finish in time, the connection is lost or [rate limited](crate::rate_limit) in the meantime, the connection is dropped
+//! and a [`NetworkEvent::Error`](crate::NetworkEvent::Error) with a [`NetworkError::Authentication`] tells why.
+//! Pending connections aren't part of [`Network::connection_ids`](crate::Network::connection_ids),
+//! and messages can't be sent to them outside of the authenticator. Only a few packets of a pending connection are buffered,
+//! it isn't read from further until the authenticator takes them.
+//!
+//! Both ends of a connection authenticate it, so the client usually sends its credentials in its authenticator,
+//! and the server checks them in its own. The local client of [`Network::connect_local`](crate::Network::connect_local)
+//! isn't authenticated.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::{collections::HashSet, sync::Arc, time::Duration};
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkMessage,
+//!     authentication::{AppNetworkAuthentication, AuthSession},
+//!     error::NetworkError,
+//!     tcp::TcpProvider,
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Login {
+//!     token: String,
+//! }
+//!
+//! impl NetworkMessage for Login {
+//!     const NAME: &'static str = "example:Login";
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct LoginAccepted;
+//!
+//! impl NetworkMessage for LoginAccepted {
+//!     const NAME: &'static str = "example:LoginAccepted";
+//! }
+//!
+//! fn build_server(app: &mut App) {
+//!     // A stand-in for the account service
+//!     let tokens = Arc::new(HashSet::from([String::from("secret")]));
+//!     app.authenticate_connections::<TcpProvider, _>(Duration::from_secs(5), move |session: AuthSession| {
+//!         let tokens = tokens.clone();
+//!         async move {
+//!             let login: Login = session.receive().await?;
+//!             if !tokens.contains(&login.token) {
+//!                 return Err(NetworkError::Error(String::from("Unknown token")));
+//!             }
+//!             session.send(LoginAccepted)
+//!         }
+//!     });
+//! }
+//!
+//! fn build_client(app: &mut App) {
+//!     app.authenticate_connections::<TcpProvider, _>(Duration::from_secs(5), |session: AuthSession| async move {
+//!         session.send(Login { token: String::from("secret") })?;
+//!         session.receive::<LoginAccepted>().await?;
+//!         Ok(())
+//!     });
+//! }
+//! ```
+
+use std::{future::Future, net::SocketAddr, pin::Pin, time::Duration};
+
+use async_channel::{Receiver, Sender};
+use bevy::prelude::{debug, App};
+
+use crate::{
+    error::NetworkError,
+    managers::{Network, NetworkProvider},
+    ConnectionId, NetworkMessage, NetworkPacket,
+};
+
+type BoxedAuthFuture = Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send>>;
+
+/// The authenticator set with [`AppNetworkAuthentication::authenticate_connections`]
+pub(crate) struct Authenticator {
+    pub(crate) timeout: Duration,
+    pub(crate) authenticate: Box<dyn Fn(AuthSession) -> BoxedAuthFuture + Send + Sync>,
+}
+
+/// A pending connection, for an authenticator to exchange messages over
+///
+/// Messages are sent and received in order, without registering them with
+/// [`AppNetworkMessage::listen_for_message`](crate::AppNetworkMessage::listen_for_message).
+pub struct AuthSession {
+    pub(crate) conn_id: ConnectionId,
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) incoming: Receiver<NetworkPacket>,
+    pub(crate) outgoing: Sender<NetworkPacket>,
+}
+
+impl AuthSession {
+    /// The id the connection will have once it is established
+    pub fn connection_id(&self) -> ConnectionId {
+        self.conn_id
+    }
+
+    /// The address of the remote end, if the [`NetworkProvider`] has one
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// Send a message to the remote end
+    pub fn send<T: NetworkMessage>(&self, message: T) -> Result<(), NetworkError> {
+        let packet = NetworkPacket {
+            kind: String::from(T::NAME),
+            data: bincode::serialize(&message).map_err(|_| NetworkError::Serialization)?,
+        };
+
+        self.outgoing
+            .try_send(packet)
+            .map_err(|_| NetworkError::ChannelClosed(self.conn_id))
+    }
+
+    /// Wait for the next message of the remote end, which has to be a `T`
+    pub async fn receive<T: NetworkMessage>(&self) -> Result<T, NetworkError> {
+        let packet = self
+            .incoming
+            .recv()
+            .await
+            .map_err(|_| NetworkError::ChannelClosed(self.conn_id))?;
+
+        if packet.kind != T::NAME {
+            return Err(NetworkError::Error(format!(
+                "Expected {} but received {}",
+                T::NAME,
+                packet.kind
+            )));
+        }
+
+        bincode::deserialize(&packet.data).map_err(|_| NetworkError::Serialization)
+    }
+}
+
+/// A utility trait on [`App`] to authenticate new connections
+pub trait AppNetworkAuthentication {
+    /// Authenticate every new connection of the network with the authenticator, before it is established
+    ///
+    /// The authenticator has to finish within the timeout, see the [module documentation](self).
+    fn authenticate_connections<NP: NetworkProvider, Fut>(
+        &mut self,
+        timeout: Duration,
+        authenticator: impl Fn(AuthSession) -> Fut + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Fut: Future<Output = Result<(), NetworkError>> + Send + 'static;
+}
+
+impl AppNetworkAuthentication for App {
+    fn authenticate_connections<NP: NetworkProvider, Fut>(
+        &mut self,
+        timeout: Duration,
+        authenticator: impl Fn(AuthSession) -> Fut + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        Fut: Future<Output = Result<(), NetworkError>> + Send + 'static,
+    {
+        let mut network = self.world_mut().get_resource_mut::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before authenticating connections.");
+
+        debug!("Authenticating new connections");
+
+        assert!(
+            network.authenticator.is_none(),
+            "Duplicate authenticator for the network"
+        );
+        network.authenticator = Some(Authenticator {
+            timeout,
+            authenticate: Box::new(move |session| Box::pin(authenticator(session))),
+        });
+        self
+    }
+}
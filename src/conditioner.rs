@@ -0,0 +1,428 @@
+//! # Link conditioner
+//!
+//! [`ConditionedProvider`] wraps any [`NetworkProvider`] and makes its connections behave like a bad network,
+//! to test how a game copes with latency, jitter, limited bandwidth, reordering, duplication and packet loss.
+//!
+//! The [`LinkConditions`] apply to the packets of each direction separately, and can be changed at any time
+//! through the [`ConditionedSettings`] resource, also for connections that are already established.
+//! Reliable providers, like tcp, never lose, duplicate or reorder packets, so only latency, jitter and bandwidth
+//! apply to them, see [`NetworkProvider::RELIABLE`].
+//!
+//! Packets are delayed on the [`Clock`] set with [`ConditionedSettings::with_clock`], the real time by default.
+//! Tests on a [`SimulatedRuntime`](crate::SimulatedRuntime) should pass its clock, so delays only pass when the test advances it.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use std::time::Duration;
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     EventworkPlugin,
+//!     conditioner::{ConditionedProvider, ConditionedSettings, LinkConditions},
+//!     tcp::{NetworkSettings, TcpProvider},
+//! };
+//!
+//! fn build(app: &mut App) {
+//!     app.add_plugins(EventworkPlugin::<ConditionedProvider<TcpProvider>, bevy::tasks::TaskPool>::default());
+//!     app.insert_resource(ConditionedSettings::new(
+//!         NetworkSettings::default(),
+//!         LinkConditions {
+//!             latency: Duration::from_millis(80),
+//!             jitter: Duration::from_millis(20),
+//!             ..default()
+//!         },
+//!     ));
+//!     app.add_systems(Update, degrade_connection);
+//! }
+//!
+//! fn degrade_connection(settings: Res<ConditionedSettings<NetworkSettings>>, time: Res<Time>) {
+//!     if time.elapsed_seconds() > 60.0 {
+//!         settings.set_conditions(LinkConditions {
+//!             latency: Duration::from_millis(300),
+//!             bandwidth: Some(16 * 1024),
+//!             ..settings.conditions()
+//!         });
+//!     }
+//! }
+//! ```
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    marker::PhantomData,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_channel::{unbounded, Receiver, Sender};
+use async_trait::async_trait;
+use bevy::prelude::Resource;
+use futures_lite::future;
+
+use crate::{error::NetworkError, managers::NetworkProvider, Clock, NetworkPacket};
+
+/// How bad the simulated network is, the default doesn't change anything
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinkConditions {
+    /// The time every packet is delayed by
+    pub latency: Duration,
+    /// The most a packet is randomly delayed by on top of the latency
+    pub jitter: Duration,
+    /// The bytes per second that can be sent, [`None`] for no limit
+    pub bandwidth: Option<u64>,
+    /// The chance from 0 to 1 that a packet is lost
+    pub loss: f32,
+    /// The chance from 0 to 1 that a packet is delivered twice
+    pub duplication: f32,
+    /// The chance from 0 to 1 that a packet is delayed by the latency and jitter once more, so packets sent after it overtake it
+    pub reordering: f32,
+}
+
+/// The settings of a [`ConditionedProvider`], the settings of the wrapped provider and the [`LinkConditions`]
+///
+/// Clones share the conditions, so changing them on the resource affects all connections.
+#[derive(Resource)]
+pub struct ConditionedSettings<S: Resource + Clone> {
+    /// The settings of the wrapped provider
+    pub settings: S,
+    conditions: Arc<RwLock<LinkConditions>>,
+    clock: Clock,
+}
+
+impl<S: Resource + Clone> ConditionedSettings<S> {
+    /// Use the given settings for the wrapped provider, with the given conditions
+    pub fn new(settings: S, conditions: LinkConditions) -> Self {
+        Self {
+            settings,
+            conditions: Arc::new(RwLock::new(conditions)),
+            clock: Clock::real(),
+        }
+    }
+
+    /// Delay packets on the given clock, like the [`Clock`](crate::Runtime::clock) of a [`SimulatedRuntime`](crate::SimulatedRuntime)
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The current conditions
+    pub fn conditions(&self) -> LinkConditions {
+        *self
+            .conditions
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Change the conditions, for new and established connections
+    pub fn set_conditions(&self, conditions: LinkConditions) {
+        *self
+            .conditions
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = conditions;
+    }
+}
+
+impl<S: Resource + Clone> Clone for ConditionedSettings<S> {
+    fn clone(&self) -> Self {
+        Self {
+            settings: self.settings.clone(),
+            conditions: self.conditions.clone(),
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl<S: Resource + Clone + Default> Default for ConditionedSettings<S> {
+    fn default() -> Self {
+        Self::new(S::default(), LinkConditions::default())
+    }
+}
+
+impl<S: Resource + Clone> Deref for ConditionedSettings<S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.settings
+    }
+}
+
+impl<S: Resource + Clone> DerefMut for ConditionedSettings<S> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.settings
+    }
+}
+
+/// A [`NetworkProvider`] behaving like `NP` on a bad network, see the [module documentation](self)
+pub struct ConditionedProvider<NP: NetworkProvider> {
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for ConditionedProvider<NP> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> std::fmt::Debug for ConditionedProvider<NP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConditionedProvider<{}>", std::any::type_name::<NP>())
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<NP: NetworkProvider> NetworkProvider for ConditionedProvider<NP> {
+    type NetworkSettings = ConditionedSettings<NP::NetworkSettings>;
+
+    type Socket = NP::Socket;
+
+    type ReadHalf = NP::ReadHalf;
+
+    type WriteHalf = NP::WriteHalf;
+
+    type ConnectInfo = NP::ConnectInfo;
+
+    type AcceptInfo = NP::AcceptInfo;
+
+    type AcceptStream = NP::AcceptStream;
+
+    const RELIABLE: bool = NP::RELIABLE;
+
+    const LABELLED: bool = NP::LABELLED;
+
+    async fn accept_loop(
+        accept_info: Self::AcceptInfo,
+        network_settings: Self::NetworkSettings,
+    ) -> Result<Self::AcceptStream, NetworkError> {
+        NP::accept_loop(accept_info, network_settings.settings).await
+    }
+
+    async fn connect_task(
+        connect_info: Self::ConnectInfo,
+        network_settings: Self::NetworkSettings,
+    ) -> Result<Self::Socket, NetworkError> {
+        NP::connect_task(connect_info, network_settings.settings).await
+    }
+
+    async fn recv_loop(
+        read_half: Self::ReadHalf,
+        messages: Sender<NetworkPacket>,
+        settings: Self::NetworkSettings,
+    ) {
+        let (received_tx, received_rx) = unbounded();
+        future::zip(
+            NP::recv_loop(read_half, received_tx, settings.settings),
+            condition(
+                received_rx,
+                messages,
+                settings.conditions,
+                settings.clock,
+                NP::RELIABLE,
+            ),
+        )
+        .await;
+    }
+
+    async fn send_loop(
+        write_half: Self::WriteHalf,
+        messages: Receiver<NetworkPacket>,
+        settings: Self::NetworkSettings,
+    ) {
+        let (conditioned_tx, conditioned_rx) = unbounded();
+        future::zip(
+            condition(
+                messages,
+                conditioned_tx,
+                settings.conditions,
+                settings.clock,
+                NP::RELIABLE,
+            ),
+            NP::send_loop(write_half, conditioned_rx, settings.settings),
+        )
+        .await;
+    }
+
+    fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf) {
+        NP::split(combined)
+    }
+
+    fn peer_addr(socket: &Self::Socket) -> Option<SocketAddr> {
+        NP::peer_addr(socket)
+    }
+}
+
+/// A packet waiting to be passed on
+struct Delayed {
+    /// In the time of the [`Clock`]
+    at: Duration,
+    /// Keeps packets due at the same time in order
+    sequence: u64,
+    packet: NetworkPacket,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.at, self.sequence).cmp(&(other.at, other.sequence))
+    }
+}
+
+enum Step {
+    Received(Option<NetworkPacket>),
+    Due,
+}
+
+/// Passes packets from `incoming` to `outgoing`, applying the conditions
+async fn condition(
+    incoming: Receiver<NetworkPacket>,
+    outgoing: Sender<NetworkPacket>,
+    conditions: Arc<RwLock<LinkConditions>>,
+    clock: Clock,
+    reliable: bool,
+) {
+    let mut rng = fastrand::Rng::new();
+    let mut queue = BinaryHeap::new();
+    let mut sequence = 0;
+    let mut last_at = clock.now();
+    let mut link_free_at = clock.now();
+    let mut open = true;
+
+    while open || !queue.is_empty() {
+        let next_at = queue
+            .peek()
+            .map(|Reverse(delayed): &Reverse<Delayed>| delayed.at);
+        let step = match next_at {
+            Some(at) if open => {
+                future::or(
+                    async { Step::Received(incoming.recv().await.ok()) },
+                    async {
+                        clock.sleep_until(at).await;
+                        Step::Due
+                    },
+                )
+                .await
+            }
+            Some(at) => {
+                clock.sleep_until(at).await;
+                Step::Due
+            }
+            None => Step::Received(incoming.recv().await.ok()),
+        };
+
+        match step {
+            Step::Received(None) => open = false,
+            Step::Received(Some(packet)) => {
+                let conditions = *conditions
+                    .read()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let now = clock.now();
+
+                if !reliable && rng.f32() < conditions.loss {
+                    continue;
+                }
+
+                let sent_at = match conditions.bandwidth {
+                    Some(bandwidth) => {
+                        let transfer = packet.data.len() as f64 / bandwidth.max(1) as f64;
+                        link_free_at = link_free_at.max(now) + Duration::from_secs_f64(transfer);
+                        link_free_at
+                    }
+                    None => now,
+                };
+                let mut at = sent_at + conditions.latency + conditions.jitter.mul_f32(rng.f32());
+                if reliable {
+                    // Jitter can't overtake earlier packets on a reliable connection
+                    at = at.max(last_at);
+                    last_at = at;
+                } else if rng.f32() < conditions.reordering {
+                    at += conditions.latency + conditions.jitter;
+                }
+
+                if !reliable && rng.f32() < conditions.duplication {
+                    queue.push(Reverse(Delayed {
+                        at,
+                        sequence,
+                        packet: packet.clone(),
+                    }));
+                    sequence += 1;
+                }
+                queue.push(Reverse(Delayed {
+                    at,
+                    sequence,
+                    packet,
+                }));
+                sequence += 1;
+            }
+            Step::Due => {
+                let now = clock.now();
+                while queue
+                    .peek()
+                    .is_some_and(|Reverse(delayed)| delayed.at <= now)
+                {
+                    let Some(Reverse(delayed)) = queue.pop() else {
+                        break;
+                    };
+                    if outgoing.send(delayed.packet).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Runtime, SimulatedRuntime};
+
+    #[test]
+    fn delays_packets_on_the_clock() {
+        let runtime = SimulatedRuntime::new();
+        let conditions = Arc::new(RwLock::new(LinkConditions {
+            latency: Duration::from_millis(100),
+            ..Default::default()
+        }));
+        let (incoming_tx, incoming_rx) = unbounded();
+        let (outgoing_tx, outgoing_rx) = unbounded();
+        let _task = runtime.spawn(condition(
+            incoming_rx,
+            outgoing_tx,
+            conditions,
+            runtime.clock(),
+            true,
+        ));
+
+        incoming_tx
+            .try_send(NetworkPacket {
+                kind: String::from("test"),
+                data: vec![1, 2, 3],
+            })
+            .expect("The conditioner stopped");
+        runtime.advance(Duration::from_millis(99));
+        assert!(outgoing_rx.try_recv().is_err());
+
+        runtime.advance(Duration::from_millis(1));
+        let packet = outgoing_rx
+            .try_recv()
+            .expect("The packet wasn't delayed by the latency");
+        assert_eq!(packet.data, vec![1, 2, 3]);
+    }
+}
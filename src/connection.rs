@@ -0,0 +1,164 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use bevy::{
+    prelude::{Component, Entity},
+    utils::Instant,
+};
+
+use crate::{
+    error::NetworkError,
+    managers::{Network, NetworkProvider},
+    ConnectionId, NetworkPacket,
+};
+
+/// Information about a connection that is known once it has been established
+#[derive(Debug, Clone)]
+pub struct ConnectionMetadata {
+    pub(crate) peer_addr: Option<SocketAddr>,
+    pub(crate) connected_at: Instant,
+}
+
+impl ConnectionMetadata {
+    /// The address of the remote end, if the [`NetworkProvider`] has one
+    #[inline(always)]
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer_addr
+    }
+
+    /// When the connection was established
+    #[inline(always)]
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
+    }
+}
+
+/// Traffic counters of a connection, updated live by the connection's tasks
+#[derive(Debug, Default)]
+pub struct ConnectionStats {
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    messages_rejected: AtomicU64,
+    packets_rate_limited: AtomicU64,
+}
+
+impl ConnectionStats {
+    /// The amount of packets handed to the provider to be sent
+    pub fn packets_sent(&self) -> u64 {
+        self.packets_sent.load(Ordering::Relaxed)
+    }
+
+    /// The amount of packets received from the provider
+    pub fn packets_received(&self) -> u64 {
+        self.packets_received.load(Ordering::Relaxed)
+    }
+
+    /// The amount of payload bytes handed to the provider to be sent
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// The amount of payload bytes received from the provider
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// The amount of received messages that were dropped because the connection wasn't allowed to send them,
+    /// see [`crate::permissions`]
+    pub fn messages_rejected(&self) -> u64 {
+        self.messages_rejected.load(Ordering::Relaxed)
+    }
+
+    /// The amount of received packets that were dropped because the connection exceeded its rate limits,
+    /// see [`crate::rate_limit`]
+    pub fn packets_rate_limited(&self) -> u64 {
+        self.packets_rate_limited.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_sent(&self, packet: &NetworkPacket) {
+        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add(packet.data.len() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_received(&self, packet: &NetworkPacket) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(packet.data.len() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rejected(&self) {
+        self.messages_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_rate_limited(&self) {
+        self.packets_rate_limited.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The component on the entities spawned for every connection, when enabled with
+/// [`EventworkPlugin::with_connection_entities`](crate::EventworkPlugin::with_connection_entities).
+///
+/// The entity is despawned when the connection is lost.
+#[derive(Component, Debug, Clone)]
+pub struct NetworkConnection {
+    pub(crate) id: ConnectionId,
+    pub(crate) metadata: ConnectionMetadata,
+    pub(crate) stats: Arc<ConnectionStats>,
+}
+
+impl NetworkConnection {
+    /// The id of the connection
+    #[inline(always)]
+    pub fn id(&self) -> ConnectionId {
+        self.id
+    }
+
+    /// Information about the connection
+    #[inline(always)]
+    pub fn metadata(&self) -> &ConnectionMetadata {
+        &self.metadata
+    }
+
+    /// Live traffic counters of the connection
+    #[inline(always)]
+    pub fn stats(&self) -> &ConnectionStats {
+        &self.stats
+    }
+}
+
+/// Anything that can be used to address a connection of a [`Network`], a [`ConnectionId`] or the [`Entity`] of its [`NetworkConnection`]
+pub trait ConnectionTarget {
+    /// Find the id of the connection in the given network
+    fn connection_id<NP: NetworkProvider>(
+        &self,
+        network: &Network<NP>,
+    ) -> Result<ConnectionId, NetworkError>;
+}
+
+impl ConnectionTarget for ConnectionId {
+    fn connection_id<NP: NetworkProvider>(
+        &self,
+        _network: &Network<NP>,
+    ) -> Result<ConnectionId, NetworkError> {
+        Ok(*self)
+    }
+}
+
+impl ConnectionTarget for Entity {
+    fn connection_id<NP: NetworkProvider>(
+        &self,
+        network: &Network<NP>,
+    ) -> Result<ConnectionId, NetworkError> {
+        network
+            .connection_of_entity(*self)
+            .ok_or(NetworkError::EntityNotFound(*self))
+    }
+}
@@ -1,6 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, net::SocketAddr};
 
-use crate::ConnectionId;
+use bevy::prelude::Entity;
+
+use crate::{admission::RejectReason, ConnectionId};
 
 /// Internal errors used by Spicy
 #[derive(Debug)]
@@ -14,6 +16,9 @@ pub enum NetworkError {
     /// Connection couldn't be found.
     ConnectionNotFound(ConnectionId),
 
+    /// No connection belongs to the entity.
+    EntityNotFound(Entity),
+
     /// Failed to send across channel because it was closed.
     ChannelClosed(ConnectionId),
 
@@ -28,6 +33,18 @@ pub enum NetworkError {
 
     /// Serialization error
     Serialization,
+
+    /// An error occured when creating or opening a traffic recording.
+    Recording(std::io::Error),
+
+    /// A new connection failed to authenticate and was dropped, with the reason.
+    Authentication(ConnectionId, String),
+
+    /// A connection started to exceed its rate limit for the kind of message.
+    RateLimited(ConnectionId, String),
+
+    /// A socket accepted while listening was rejected and closed, with its address if the provider has one.
+    Rejected(Option<SocketAddr>, RejectReason),
 }
 
 impl Display for NetworkError {
@@ -41,6 +58,10 @@ impl Display for NetworkError {
             Self::ConnectionNotFound(id) => {
                 f.write_fmt(format_args!("Could not find connection with id: {0}", id))
             }
+            Self::EntityNotFound(entity) => f.write_fmt(format_args!(
+                "Could not find a connection for entity: {0}",
+                entity
+            )),
             Self::ChannelClosed(id) => {
                 f.write_fmt(format_args!("Connection closed with id: {0}", id))
             }
@@ -56,6 +77,22 @@ impl Display for NetworkError {
                 f.write_fmt(format_args!("Attempted to send data over closed channel"))
             }
             Self::Serialization => f.write_fmt(format_args!("Failed to serialize")),
+            Self::Recording(e) => f.write_fmt(format_args!(
+                "An error occured when creating or opening a traffic recording: {0}",
+                e
+            )),
+            Self::Authentication(id, reason) => f.write_fmt(format_args!(
+                "Could not authenticate connection with id: {0}: {1}",
+                id, reason
+            )),
+            Self::RateLimited(id, kind) => f.write_fmt(format_args!(
+                "Connection with id: {0} exceeded its rate limit for: {1}",
+                id, kind
+            )),
+            Self::Rejected(peer_addr, reason) => f.write_fmt(format_args!(
+                "Rejected a new connection from: {0:?}: {1}",
+                peer_addr, reason
+            )),
         }
     }
 }
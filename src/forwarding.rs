@@ -0,0 +1,179 @@
+//! # Event forwarding
+//!
+//! Bridges regular Bevy [`Event`]s over the network, without writing the systems that send and re-emit them by hand.
+//!
+//! - [`AppNetworkEventForwarding::forward_event`] sends every local event to a [`ForwardTarget`].
+//! - [`AppNetworkEventForwarding::listen_for_forwarded_event`] emits every received event as a plain event,
+//!   next to the usual [`NetworkData`](crate::NetworkData) event which also tells who sent it.
+//!
+//! Events that were received over the network are never forwarded again, so an app can both forward and listen for the same event.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkMessage,
+//!     tcp::TcpProvider,
+//!     forwarding::{AppNetworkEventForwarding, ForwardTarget},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Event, Serialize, Deserialize, Clone)]
+//! struct Jump;
+//!
+//! impl NetworkMessage for Jump {
+//!     const NAME: &'static str = "example:Jump";
+//! }
+//!
+//! fn build_client(app: &mut App) {
+//!     app.add_event::<Jump>();
+//!     app.forward_event::<Jump, TcpProvider>(ForwardTarget::Server);
+//! }
+//!
+//! fn build_server(app: &mut App) {
+//!     app.listen_for_forwarded_event::<Jump, TcpProvider>();
+//! }
+//! ```
+
+use std::{collections::HashSet, marker::PhantomData};
+
+use bevy::prelude::{
+    debug, App, Event, EventReader, Events, IntoSystemConfigs, Res, ResMut, Resource,
+};
+
+use crate::{
+    labelled::{NetworkDataReader, NetworkEventWriter},
+    managers::{
+        network::{register_message, AppNetworkMessage},
+        Network, NetworkProvider,
+    },
+    ConnectionId, EventworkSchedules, EventworkSet, NetworkEvent, NetworkMessage,
+};
+
+/// Where [`AppNetworkEventForwarding::forward_event`] sends events to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ForwardTarget {
+    /// The server this app is connected to, only forwards while the network isn't listening
+    Server,
+    /// Every connected client, only forwards while the network is listening
+    AllClients,
+    /// A single connection
+    Connection(ConnectionId),
+}
+
+/// The ids of events that were received over the network, so they don't get forwarded again
+#[derive(Resource)]
+struct ReceivedEvents<E: Event> {
+    ids: HashSet<usize>,
+    marker: PhantomData<E>,
+}
+
+impl<E: Event> Default for ReceivedEvents<E> {
+    fn default() -> Self {
+        Self {
+            ids: HashSet::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// A utility trait on [`App`] to send and receive Bevy [`Event`]s over the network
+pub trait AppNetworkEventForwarding {
+    /// Send every event of type `E` to the given target
+    ///
+    /// ## Details
+    /// This will:
+    /// - Send every `E` to the target, after it was sent locally
+    /// - Report failed sends as [`NetworkEvent::Error`]
+    /// - Internal bookkeeping
+    fn forward_event<E, NP: NetworkProvider>(&mut self, target: ForwardTarget) -> &mut Self
+    where
+        E: Event + NetworkMessage + Clone;
+
+    /// Emit every received event of type `E` as a local event
+    ///
+    /// ## Details
+    /// This will:
+    /// - Register `E` with [`AppNetworkMessage::listen_for_message`], so [`NetworkData<E>`](crate::NetworkData) is emitted as usual
+    /// - Emit a plain `E` for every received one
+    /// - Internal bookkeeping
+    fn listen_for_forwarded_event<E, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        E: Event + NetworkMessage + Clone;
+}
+
+impl AppNetworkEventForwarding for App {
+    fn forward_event<E, NP: NetworkProvider>(&mut self, target: ForwardTarget) -> &mut Self
+    where
+        E: Event + NetworkMessage + Clone,
+    {
+        debug!("Forwarding events of {} to {:?}", E::NAME, target);
+
+        self.add_event::<E>();
+        self.init_resource::<ReceivedEvents<E>>();
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.add_systems(
+            schedules.send,
+            (move |net: Res<Network<NP>>,
+                   mut events: EventReader<E>,
+                   received: Res<ReceivedEvents<E>>,
+                   mut network_events: NetworkEventWriter<NP>| {
+                for (event, id) in events.read_with_id() {
+                    if received.ids.contains(&id.id) {
+                        continue;
+                    }
+
+                    match target {
+                        ForwardTarget::Server if !net.is_listening() => {
+                            net.broadcast(event.clone())
+                        }
+                        // The local client is this app, it already has the event
+                        ForwardTarget::AllClients if net.is_listening() => {
+                            net.broadcast_remote(event.clone())
+                        }
+                        ForwardTarget::Connection(conn_id) => {
+                            if let Err(err) = net.send_message(conn_id, event.clone()) {
+                                network_events.send(NetworkEvent::Error(err));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            })
+            .in_set(EventworkSet::Send),
+        )
+    }
+
+    fn listen_for_forwarded_event<E, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        E: Event + NetworkMessage + Clone,
+    {
+        self.listen_for_message::<E, NP>();
+        self.add_event::<E>();
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.add_systems(
+            schedules.receive,
+            emit_forwarded_events::<E, NP>
+                .after(register_message::<E, NP>)
+                .in_set(EventworkSet::Receive),
+        )
+    }
+}
+
+fn emit_forwarded_events<E, NP: NetworkProvider>(
+    mut network_data: NetworkDataReader<E, NP>,
+    mut events: ResMut<Events<E>>,
+    received: Option<ResMut<ReceivedEvents<E>>>,
+) where
+    E: Event + NetworkMessage + Clone,
+{
+    let ids = events.send_batch(network_data.read().map(|data| E::clone(data)));
+
+    if let Some(mut received) = received {
+        // Events older than that have been dropped, and can't be forwarded anymore
+        let oldest = events.oldest_id();
+        received.ids.retain(|id| *id >= oldest);
+        received.ids.extend(ids.map(|id| id.id));
+    }
+}
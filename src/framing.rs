@@ -0,0 +1,97 @@
+//! The framing shared by the tcp providers: every packet is sent as its bincode encoding, prefixed with its length
+
+use async_channel::{Receiver, Sender};
+use bevy::log::{debug, error, info, trace};
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::NetworkPacket;
+
+/// Reads packets and hands them to eventwork, until the stream is closed or sends an invalid packet
+pub(crate) async fn recv_packets(
+    mut read_half: impl AsyncRead + Unpin,
+    messages: Sender<NetworkPacket>,
+    max_packet_length: usize,
+) {
+    let mut buffer = vec![0; max_packet_length];
+    loop {
+        trace!("Reading message length");
+        let mut length = [0; 8];
+        match read_half.read_exact(&mut length).await {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                // EOF, meaning the TCP stream has closed.
+                info!("Client disconnected");
+                break;
+            }
+            Err(err) => {
+                error!("Encountered error while fetching length: {}", err);
+                break;
+            }
+        }
+        let length = u64::from_le_bytes(length) as usize;
+        trace!("Message length: {}", length);
+
+        if length > max_packet_length {
+            error!(
+                "Received too large packet: {} > {}",
+                length, max_packet_length
+            );
+            break;
+        }
+
+        if let Err(err) = read_half.read_exact(&mut buffer[..length]).await {
+            error!(
+                "Encountered error while fetching stream of length {}: {}",
+                length, err
+            );
+            break;
+        }
+
+        let packet: NetworkPacket = match bincode::deserialize(&buffer[..length]) {
+            Ok(packet) => packet,
+            Err(err) => {
+                error!("Failed to decode network packet from: {}", err);
+                break;
+            }
+        };
+
+        if messages.send(packet).await.is_err() {
+            error!("Failed to send decoded message to eventwork");
+            break;
+        }
+        trace!("Message deserialized and sent to eventwork");
+    }
+}
+
+/// Writes the packets eventwork sends, until the stream is closed
+pub(crate) async fn send_packets(
+    mut write_half: impl AsyncWrite + Unpin,
+    messages: Receiver<NetworkPacket>,
+) {
+    while let Ok(message) = messages.recv().await {
+        let encoded = match bincode::serialize(&message) {
+            Ok(encoded) => encoded,
+            Err(err) => {
+                error!("Could not encode packet {:?}: {}", message, err);
+                continue;
+            }
+        };
+
+        let len = encoded.len() as u64;
+        debug!("Sending a new message of size: {}", len);
+
+        if let Err(err) = write_half.write_all(&len.to_le_bytes()).await {
+            error!("Could not send packet length: {:?}: {}", len, err);
+            break;
+        }
+
+        trace!("Sending the content of the message!");
+
+        if let Err(err) = write_half.write_all(&encoded).await {
+            error!("Could not send packet: {:?}: {}", message, err);
+            break;
+        }
+
+        trace!("Succesfully written all!");
+    }
+}
@@ -0,0 +1,424 @@
+//! # Labelled networks
+//!
+//! There is one [`Network`](crate::Network) per [`NetworkProvider`], so two networks using the same provider,
+//! like a game server and a connection to a backend both over TCP, would share everything.
+//! Wrapping the provider in [`Labelled`] turns it into a provider of its own:
+//! its network has its own connections, message registry, settings and [`EventworkPlugin`](crate::EventworkPlugin),
+//! and every API taking a provider, like [`AppNetworkMessage::listen_for_message`](crate::AppNetworkMessage::listen_for_message),
+//! works with it as is.
+//!
+//! A labelled network sends its events as [`LabelledEvent`]s and [`LabelledData`] of its provider, instead of
+//! [`NetworkEvent`]s and [`NetworkData`], so systems only see the events of the network they are interested in.
+//! Both deref to the event they wrap. Every network counts its connection ids on its own,
+//! so the same [`ConnectionId`](crate::ConnectionId) can belong to connections of different networks.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     AppNetworkMessage, EventworkPlugin, NetworkEvent, NetworkMessage,
+//!     labelled::{Labelled, LabelledData, LabelledEvent, LabelledSettings, NetworkLabel},
+//!     tcp::{NetworkSettings, TcpProvider},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! struct Backend;
+//!
+//! impl NetworkLabel for Backend {}
+//!
+//! type BackendProvider = Labelled<TcpProvider, Backend>;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct MatchResult;
+//!
+//! impl NetworkMessage for MatchResult {
+//!     const NAME: &'static str = "example:MatchResult";
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     // The game server
+//!     app.add_plugins(EventworkPlugin::<TcpProvider, bevy::tasks::TaskPool>::default());
+//!     app.insert_resource(NetworkSettings::default());
+//!
+//!     // The connection to the backend
+//!     app.add_plugins(EventworkPlugin::<BackendProvider, bevy::tasks::TaskPool>::default());
+//!     app.insert_resource(LabelledSettings::<_, Backend>::new(NetworkSettings::default()));
+//!     app.listen_for_message::<MatchResult, BackendProvider>();
+//!     app.add_systems(Update, (handle_backend_events, handle_match_results));
+//! }
+//!
+//! fn handle_backend_events(mut network_events: EventReader<LabelledEvent<BackendProvider>>) {
+//!     for event in network_events.read() {
+//!         if let NetworkEvent::Disconnected(_) = **event {
+//!             warn!("Lost the connection to the backend");
+//!         }
+//!     }
+//! }
+//!
+//! fn handle_match_results(mut results: EventReader<LabelledData<MatchResult, BackendProvider>>) {
+//!     for result in results.read() {
+//!         info!("Match result from the backend: {}", result.source());
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    net::SocketAddr,
+    ops::{Deref, DerefMut},
+};
+
+use async_channel::{Receiver, Sender};
+use async_trait::async_trait;
+use bevy::{
+    ecs::{event::ManualEventReader, system::SystemParam},
+    prelude::{App, Event, Events, Local, Res, ResMut, Resource, World},
+};
+
+use crate::{
+    error::NetworkError, managers::NetworkProvider, NetworkData, NetworkEvent, NetworkPacket,
+};
+
+/// Names a network, to tell apart networks using the same provider
+pub trait NetworkLabel: Send + Sync + 'static {}
+
+/// A [`NetworkEvent`] of the network of the [`Labelled`] provider `NP`
+#[derive(Event)]
+pub struct LabelledEvent<NP: NetworkProvider> {
+    event: NetworkEvent,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> LabelledEvent<NP> {
+    /// Get the inner event out of it
+    pub fn into_inner(self) -> NetworkEvent {
+        self.event
+    }
+}
+
+impl<NP: NetworkProvider> From<NetworkEvent> for LabelledEvent<NP> {
+    fn from(event: NetworkEvent) -> Self {
+        Self {
+            event,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> Deref for LabelledEvent<NP> {
+    type Target = NetworkEvent;
+
+    fn deref(&self) -> &Self::Target {
+        &self.event
+    }
+}
+
+impl<NP: NetworkProvider> Debug for LabelledEvent<NP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.event.fmt(f)
+    }
+}
+
+/// [`NetworkData`] received by the network of the [`Labelled`] provider `NP`
+#[derive(Event)]
+pub struct LabelledData<T: Send + Sync + 'static, NP: NetworkProvider> {
+    data: NetworkData<T>,
+    marker: PhantomData<NP>,
+}
+
+impl<T: Send + Sync + 'static, NP: NetworkProvider> LabelledData<T, NP> {
+    /// Get the inner data out of it
+    pub fn into_inner(self) -> NetworkData<T> {
+        self.data
+    }
+}
+
+impl<T: Send + Sync + 'static, NP: NetworkProvider> From<NetworkData<T>> for LabelledData<T, NP> {
+    fn from(data: NetworkData<T>) -> Self {
+        Self {
+            data,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static, NP: NetworkProvider> Deref for LabelledData<T, NP> {
+    type Target = NetworkData<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl<T: Send + Sync + 'static + Debug, NP: NetworkProvider> Debug for LabelledData<T, NP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.data.fmt(f)
+    }
+}
+
+/// Sends the events of the network of `NP`, as [`LabelledEvent`]s if it is [labelled](NetworkProvider::LABELLED)
+#[derive(SystemParam)]
+pub(crate) struct NetworkEventWriter<'w, NP: NetworkProvider> {
+    events: Option<ResMut<'w, Events<NetworkEvent>>>,
+    labelled: Option<ResMut<'w, Events<LabelledEvent<NP>>>>,
+}
+
+impl<'w, NP: NetworkProvider> NetworkEventWriter<'w, NP> {
+    pub(crate) fn send(&mut self, event: NetworkEvent) {
+        if NP::LABELLED {
+            if let Some(events) = &mut self.labelled {
+                events.send(LabelledEvent::from(event));
+            }
+        } else if let Some(events) = &mut self.events {
+            events.send(event);
+        }
+    }
+}
+
+/// Reads the events of the network of `NP`, see [`NetworkEventWriter`]
+#[derive(SystemParam)]
+pub(crate) struct NetworkEventReader<'w, 's, NP: NetworkProvider> {
+    events: Option<Res<'w, Events<NetworkEvent>>>,
+    labelled: Option<Res<'w, Events<LabelledEvent<NP>>>>,
+    reader: Local<'s, ManualEventReader<NetworkEvent>>,
+    labelled_reader: Local<'s, ManualEventReader<LabelledEvent<NP>>>,
+}
+
+impl<'w, 's, NP: NetworkProvider> NetworkEventReader<'w, 's, NP> {
+    pub(crate) fn read(&mut self) -> impl Iterator<Item = &NetworkEvent> {
+        let Self {
+            events,
+            labelled,
+            reader,
+            labelled_reader,
+        } = self;
+        let events = events
+            .as_ref()
+            .filter(|_| !NP::LABELLED)
+            .map(|events| reader.read(events));
+        let labelled = labelled
+            .as_ref()
+            .filter(|_| NP::LABELLED)
+            .map(|events| labelled_reader.read(events).map(|event| &event.event));
+        events
+            .into_iter()
+            .flatten()
+            .chain(labelled.into_iter().flatten())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.read().for_each(drop);
+    }
+}
+
+/// Sends the data received by the network of `NP`, as [`LabelledData`] if it is [labelled](NetworkProvider::LABELLED)
+#[derive(SystemParam)]
+pub(crate) struct NetworkDataWriter<'w, T: Send + Sync + 'static, NP: NetworkProvider> {
+    events: Option<ResMut<'w, Events<NetworkData<T>>>>,
+    labelled: Option<ResMut<'w, Events<LabelledData<T, NP>>>>,
+}
+
+impl<'w, T: Send + Sync + 'static, NP: NetworkProvider> NetworkDataWriter<'w, T, NP> {
+    pub(crate) fn send_batch(&mut self, data: impl IntoIterator<Item = NetworkData<T>>) {
+        if NP::LABELLED {
+            if let Some(events) = &mut self.labelled {
+                events.send_batch(data.into_iter().map(LabelledData::from));
+            }
+        } else if let Some(events) = &mut self.events {
+            events.send_batch(data);
+        }
+    }
+}
+
+/// Reads the data received by the network of `NP`, see [`NetworkDataWriter`]
+#[derive(SystemParam)]
+pub(crate) struct NetworkDataReader<'w, 's, T: Send + Sync + 'static, NP: NetworkProvider> {
+    events: Option<Res<'w, Events<NetworkData<T>>>>,
+    labelled: Option<Res<'w, Events<LabelledData<T, NP>>>>,
+    reader: Local<'s, ManualEventReader<NetworkData<T>>>,
+    labelled_reader: Local<'s, ManualEventReader<LabelledData<T, NP>>>,
+}
+
+impl<'w, 's, T: Send + Sync + 'static, NP: NetworkProvider> NetworkDataReader<'w, 's, T, NP> {
+    pub(crate) fn read(&mut self) -> impl Iterator<Item = &NetworkData<T>> {
+        let Self {
+            events,
+            labelled,
+            reader,
+            labelled_reader,
+        } = self;
+        let events = events
+            .as_ref()
+            .filter(|_| !NP::LABELLED)
+            .map(|events| reader.read(events));
+        let labelled = labelled
+            .as_ref()
+            .filter(|_| NP::LABELLED)
+            .map(|events| labelled_reader.read(events).map(|data| &data.data));
+        events
+            .into_iter()
+            .flatten()
+            .chain(labelled.into_iter().flatten())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.read().for_each(drop);
+    }
+}
+
+/// Registers the events of the network of `NP`
+pub(crate) fn add_network_events<NP: NetworkProvider>(app: &mut App) {
+    // Also for labelled networks, so apps with only labelled networks can still read them
+    app.add_event::<NetworkEvent>();
+    if NP::LABELLED {
+        app.add_event::<LabelledEvent<NP>>();
+    }
+}
+
+/// Registers the event the network of `NP` sends received messages of `T` as
+pub(crate) fn add_data_event<T: Send + Sync + 'static, NP: NetworkProvider>(app: &mut App) {
+    if NP::LABELLED {
+        app.add_event::<LabelledData<T, NP>>();
+    } else {
+        app.add_event::<NetworkData<T>>();
+    }
+}
+
+/// Sends an event of the network of `NP` from exclusive access to the world
+pub(crate) fn send_network_event<NP: NetworkProvider>(world: &mut World, event: NetworkEvent) {
+    if NP::LABELLED {
+        world.send_event(LabelledEvent::<NP>::from(event));
+    } else {
+        world.send_event(event);
+    }
+}
+
+/// A [`NetworkProvider`] behaving exactly like `NP`, but with a network of its own for every label `L`
+pub struct Labelled<NP: NetworkProvider, L: NetworkLabel> {
+    marker: PhantomData<(NP, L)>,
+}
+
+impl<NP: NetworkProvider, L: NetworkLabel> Default for Labelled<NP, L> {
+    fn default() -> Self {
+        Self {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider, L: NetworkLabel> Debug for Labelled<NP, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Labelled<{}, {}>",
+            std::any::type_name::<NP>(),
+            std::any::type_name::<L>()
+        )
+    }
+}
+
+/// The settings of a [`Labelled`] network, so every label can be configured on its own
+#[derive(Resource)]
+pub struct LabelledSettings<S: Resource + Clone, L: NetworkLabel> {
+    /// The settings of the wrapped provider
+    pub settings: S,
+    marker: PhantomData<L>,
+}
+
+impl<S: Resource + Clone, L: NetworkLabel> LabelledSettings<S, L> {
+    /// Use the given settings for the network labelled `L`
+    pub fn new(settings: S) -> Self {
+        Self {
+            settings,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<S: Resource + Clone, L: NetworkLabel> Clone for LabelledSettings<S, L> {
+    fn clone(&self) -> Self {
+        Self::new(self.settings.clone())
+    }
+}
+
+impl<S: Resource + Clone + Default, L: NetworkLabel> Default for LabelledSettings<S, L> {
+    fn default() -> Self {
+        Self::new(S::default())
+    }
+}
+
+impl<S: Resource + Clone, L: NetworkLabel> Deref for LabelledSettings<S, L> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.settings
+    }
+}
+
+impl<S: Resource + Clone, L: NetworkLabel> DerefMut for LabelledSettings<S, L> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.settings
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl<NP: NetworkProvider, L: NetworkLabel> NetworkProvider for Labelled<NP, L> {
+    type NetworkSettings = LabelledSettings<NP::NetworkSettings, L>;
+
+    type Socket = NP::Socket;
+
+    type ReadHalf = NP::ReadHalf;
+
+    type WriteHalf = NP::WriteHalf;
+
+    type ConnectInfo = NP::ConnectInfo;
+
+    type AcceptInfo = NP::AcceptInfo;
+
+    type AcceptStream = NP::AcceptStream;
+
+    const RELIABLE: bool = NP::RELIABLE;
+
+    const LABELLED: bool = true;
+
+    async fn accept_loop(
+        accept_info: Self::AcceptInfo,
+        network_settings: Self::NetworkSettings,
+    ) -> Result<Self::AcceptStream, NetworkError> {
+        NP::accept_loop(accept_info, network_settings.settings).await
+    }
+
+    async fn connect_task(
+        connect_info: Self::ConnectInfo,
+        network_settings: Self::NetworkSettings,
+    ) -> Result<Self::Socket, NetworkError> {
+        NP::connect_task(connect_info, network_settings.settings).await
+    }
+
+    async fn recv_loop(
+        read_half: Self::ReadHalf,
+        messages: Sender<NetworkPacket>,
+        settings: Self::NetworkSettings,
+    ) {
+        NP::recv_loop(read_half, messages, settings.settings).await
+    }
+
+    async fn send_loop(
+        write_half: Self::WriteHalf,
+        messages: Receiver<NetworkPacket>,
+        settings: Self::NetworkSettings,
+    ) {
+        NP::send_loop(write_half, messages, settings.settings).await
+    }
+
+    fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf) {
+        NP::split(combined)
+    }
+
+    fn peer_addr(socket: &Self::Socket) -> Option<SocketAddr> {
+        NP::peer_addr(socket)
+    }
+}
@@ -21,6 +21,21 @@ can start receiving packets as events of [`NetworkData<T>`].
 
 This plugin also supports Request/Response style messages, see that modules documentation for further info: **[Request Documentation](https://docs.rs/bevy_eventwork/latest/bevy_eventwork/managers/network_request/index.html)**
 
+Requests that answer with a stream of items instead of a single response are covered in [`managers::network_stream`].
+
+To keep components of server entities in sync on the clients, see [`replication`].
+To send messages to groups of connections, see [`rooms`].
+To send regular Bevy events over the network, see [`forwarding`].
+To run several networks with the same provider in one app, see [`labelled`].
+To test on a simulated bad network, see [`conditioner`].
+To record the traffic of a session and replay it later, see [`recording`].
+To authenticate new connections before they are established, see [`authentication`].
+To only accept some messages from some connections, see [`permissions`].
+To protect against connections flooding the network, see [`rate_limit`].
+To only let some clients connect to a server, or only so many, see [`admission`].
+
+Eventwork's systems run in the [`EventworkSet`]s, use them to order your own systems around receiving and sending.
+
 ## Example Client
 ```rust,no_run
 use bevy::prelude::*;
@@ -142,40 +157,73 @@ fn handle_connection_events(
 As you can see, they are both quite similar, and provide everything a basic networked game needs.
 
 Currently, Bevy's [TaskPool](bevy::tasks::TaskPool) is the default runtime used by Eventwork.
+To keep networking off Bevy's task pools, use a [`ThreadedRuntime`] with threads of its own.
+For reproducible tests, the [`SimulatedRuntime`] only runs tasks when stepped, with a virtual clock.
+With the `tokio` feature, a `tokio::runtime::Handle` can be used instead, together with the provider in `tokio_tcp`.
 */
 
+pub mod admission;
+pub mod authentication;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod conditioner;
+/// Contains the components and statistics describing a single connection.
+pub mod connection;
 /// Contains error enum.
 pub mod error;
+pub mod forwarding;
+pub mod labelled;
 mod network_message;
+pub mod permissions;
+pub mod rate_limit;
+pub mod recording;
 
 /// Contains all functionality for starting a server or client, sending, and recieving messages from clients.
 pub mod managers;
 pub use managers::{network::AppNetworkMessage, Network};
 
+pub mod replication;
+pub mod rooms;
+pub mod state;
+
 mod runtime;
+use connection::{ConnectionMetadata, ConnectionStats};
+pub use connection::{ConnectionTarget, NetworkConnection};
 use managers::NetworkProvider;
 pub use runtime::EventworkRuntime;
 use runtime::JoinHandle;
 pub use runtime::Runtime;
+pub use runtime::{Clock, SimulatedClock, SimulatedRuntime, SimulatedSleep};
+#[cfg(not(target_arch = "wasm32"))]
+pub use runtime::{ThreadedRuntime, ThreadedRuntimeBuilder};
 
 use std::{
     fmt::{Debug, Display},
     marker::PhantomData,
+    sync::Arc,
 };
 
 pub use async_channel;
 use async_channel::{unbounded, Receiver, Sender};
 pub use async_trait::async_trait;
-use bevy::prelude::*;
+use bevy::{
+    ecs::schedule::{InternedScheduleLabel, ScheduleLabel},
+    prelude::*,
+};
 use error::NetworkError;
 pub use network_message::NetworkMessage;
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
 
+#[cfg(any(feature = "tcp", feature = "tokio"))]
+mod framing;
 #[cfg(feature = "tcp")]
 /// A default tcp provider to help get you started.
 pub mod tcp;
 
+#[cfg(feature = "tokio")]
+/// A tcp provider for apps running on tokio.
+pub mod tokio_tcp;
+
 struct AsyncChannel<T> {
     pub(crate) sender: Sender<T>,
     pub(crate) receiver: Receiver<T>,
@@ -189,7 +237,7 @@ impl<T> AsyncChannel<T> {
     }
 }
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 /// A [`ConnectionId`] denotes a single connection
 pub struct ConnectionId {
     /// The key of the connection.
@@ -202,13 +250,25 @@ impl Display for ConnectionId {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 /// [`NetworkPacket`]s are untyped packets to be sent over the wire
 pub struct NetworkPacket {
     kind: String,
     data: Vec<u8>,
 }
 
+impl NetworkPacket {
+    /// The [`NetworkMessage::NAME`] of the message in this packet
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    /// The serialized message
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
 impl Debug for NetworkPacket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("NetworkPacket")
@@ -258,33 +318,167 @@ impl<T> NetworkData<T> {
 }
 
 struct Connection {
-    receive_task: Box<dyn JoinHandle>,
-    map_receive_task: Box<dyn JoinHandle>,
-    send_task: Box<dyn JoinHandle>,
+    tasks: Vec<Box<dyn JoinHandle>>,
     send_message: Sender<NetworkPacket>,
+    metadata: ConnectionMetadata,
+    stats: Arc<ConnectionStats>,
+    entity: Option<Entity>,
 }
 
 impl Connection {
     fn stop(mut self) {
-        self.receive_task.abort();
-        self.send_task.abort();
-        self.map_receive_task.abort();
+        for task in self.tasks.iter_mut() {
+            task.abort();
+        }
     }
 }
-#[derive(Default, Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug)]
 /// The plugin to add to your bevy [`App``] when you want
 /// to instantiate a server
-pub struct EventworkPlugin<NP: NetworkProvider, RT: Runtime = bevy::tasks::TaskPool>(
-    PhantomData<(NP, RT)>,
-);
+pub struct EventworkPlugin<NP: NetworkProvider, RT: Runtime = bevy::tasks::TaskPool> {
+    connection_entities: bool,
+    #[cfg(feature = "state")]
+    connection_state: bool,
+    schedules: Option<EventworkSchedules>,
+    marker: PhantomData<(NP, RT)>,
+}
+
+// Not derived, runtimes like tokio's `Handle` don't implement `Default`
+impl<NP: NetworkProvider, RT: Runtime> Default for EventworkPlugin<NP, RT> {
+    fn default() -> Self {
+        Self {
+            connection_entities: false,
+            #[cfg(feature = "state")]
+            connection_state: false,
+            schedules: None,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// The system sets eventwork's systems run in.
+///
+/// [`Connections`](EventworkSet::Connections), [`Receive`](EventworkSet::Receive) and [`Requests`](EventworkSet::Requests)
+/// run in that order in the receive schedule, [`PreUpdate`] by default.
+/// [`Send`](EventworkSet::Send) runs in the send schedule, [`PostUpdate`] by default.
+/// Both can be changed with [`EventworkPlugin::with_schedules`].
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventworkSet {
+    /// Accepts new connections and cleans up lost ones, sending [`NetworkEvent`]s
+    Connections,
+    /// Decodes received messages into [`NetworkData`] events, and applies replicated state
+    Receive,
+    /// Turns received requests and responses into their events and runs request handlers
+    Requests,
+    /// Sends the messages queued by [`NetworkWriter`](managers::network_writer::NetworkWriter)s, replicated state and forwarded events
+    Send,
+}
+
+/// The schedules eventwork's systems run in, see [`EventworkSet`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EventworkSchedules {
+    pub(crate) receive: InternedScheduleLabel,
+    pub(crate) send: InternedScheduleLabel,
+}
+
+impl Default for EventworkSchedules {
+    fn default() -> Self {
+        Self {
+            receive: PreUpdate.intern(),
+            send: PostUpdate.intern(),
+        }
+    }
+}
+
+impl EventworkSchedules {
+    /// The schedules the network of the given provider was configured with
+    pub(crate) fn of<NP: NetworkProvider>(app: &App) -> Self {
+        app.world()
+            .get_resource::<Network<NP>>()
+            .map(|network| network.schedules)
+            .unwrap_or_default()
+    }
+}
+
+impl<NP: NetworkProvider, RT: Runtime> EventworkPlugin<NP, RT> {
+    /// Spawn an entity with a [`NetworkConnection`] component for every new connection,
+    /// and despawn it once the connection is lost.
+    ///
+    /// These entities can be used in place of a [`ConnectionId`] in [`Network::send_message`].
+    pub fn with_connection_entities(mut self) -> Self {
+        self.connection_entities = true;
+        self
+    }
+
+    /// Drive the [`ClientConnectionState`](state::ClientConnectionState) from this network's connections.
+    ///
+    /// Requires the `StatesPlugin`, which is part of the `DefaultPlugins`.
+    #[cfg(feature = "state")]
+    pub fn with_connection_state(mut self) -> Self {
+        self.connection_state = true;
+        self
+    }
+
+    /// Run the [`EventworkSet`]s in the given schedules instead of [`PreUpdate`] and [`PostUpdate`],
+    /// for example [`FixedPreUpdate`] and [`FixedPostUpdate`] for lockstep games.
+    ///
+    /// Everything registered for this provider afterwards, messages, requests and replication, follows these schedules.
+    pub fn with_schedules(mut self, receive: impl ScheduleLabel, send: impl ScheduleLabel) -> Self {
+        self.schedules = Some(EventworkSchedules {
+            receive: receive.intern(),
+            send: send.intern(),
+        });
+        self
+    }
+}
 
 impl<NP: NetworkProvider + Default, RT: Runtime> Plugin for EventworkPlugin<NP, RT> {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Network::new(NP::default()));
-        app.add_event::<NetworkEvent>();
+        let schedules = self.schedules.unwrap_or_default();
+        let mut network = Network::new(NP::default());
+        network.spawn_connection_entities = self.connection_entities;
+        network.schedules = schedules;
+        app.insert_resource(network);
+        labelled::add_network_events::<NP>(app);
+        app.init_resource::<Clock>();
+        app.configure_sets(
+            schedules.receive,
+            (
+                EventworkSet::Connections,
+                EventworkSet::Receive,
+                EventworkSet::Requests,
+            )
+                .chain(),
+        );
+        if schedules.receive == schedules.send {
+            app.configure_sets(
+                schedules.send,
+                EventworkSet::Send.after(EventworkSet::Requests),
+            );
+        }
         app.add_systems(
-            PreUpdate,
-            managers::network::handle_new_incoming_connections::<NP, RT>,
+            schedules.receive,
+            (
+                runtime::update_clock::<RT>,
+                recording::advance_traffic_frame::<NP>,
+                managers::network::handle_new_incoming_connections::<NP, RT>,
+            )
+                .chain()
+                .in_set(EventworkSet::Connections),
         );
+        app.add_systems(
+            schedules.send,
+            managers::network_writer::flush_outgoing_messages::<NP>.in_set(EventworkSet::Send),
+        );
+        #[cfg(feature = "state")]
+        if self.connection_state {
+            app.init_state::<state::ClientConnectionState>();
+            app.add_systems(
+                schedules.receive,
+                state::update_connection_state::<NP>
+                    .in_set(EventworkSet::Connections)
+                    .after(managers::network::handle_new_incoming_connections::<NP, RT>),
+            );
+        }
     }
 }
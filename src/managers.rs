@@ -1,19 +1,33 @@
-use std::sync::{atomic::AtomicU32, Arc};
+use std::{
+    any::Any,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32},
+        Arc,
+    },
+    time::Duration,
+};
 
 use async_channel::{Receiver, Sender};
 use async_trait::async_trait;
-use bevy::prelude::Resource;
-use dashmap::DashMap;
+use bevy::prelude::{Entity, Resource};
+use dashmap::{DashMap, DashSet};
 use futures_lite::Stream;
 
 use crate::{
-    error::NetworkError, runtime::JoinHandle, AsyncChannel, Connection, ConnectionId, NetworkPacket,
+    authentication::Authenticator, error::NetworkError, rate_limit::SharedRateLimits,
+    recording::TrafficRecorder, runtime::JoinHandle, AsyncChannel, Connection, ConnectionId,
+    EventworkSchedules, NetworkPacket,
 };
 
 /// Contains logic for using [`Network`]
 pub mod network;
 /// Contains logic for making requests with expected responses
 pub mod network_request;
+/// Contains logic for making requests with a stream of expected responses
+pub mod network_stream;
+/// Contains a system parameter queueing messages to be sent
+pub mod network_writer;
 
 /// An instance of a Network that uses the provided [`NetworkProvider`] to drive itself.
 ///
@@ -23,17 +37,61 @@ pub mod network_request;
 /// - Connect to a server using [`Network::connect`]
 /// - Send new messages using [`Network::send_message`]
 /// - Send broadcasts to all connected clients using [`Network::broadcast`]
+/// - Play on your own server using [`Network::connect_local`]
+/// - Record the traffic for a later replay using [`Network::record_traffic`]
+///
+/// New connections can be authenticated before they are established, see [`crate::authentication`],
+/// and the packets they may send can be limited, see [`crate::rate_limit`].
+/// Which clients may connect at all is decided by the [`AdmissionPolicy`](crate::admission::AdmissionPolicy).
 #[derive(Resource)]
 pub struct Network<NP: NetworkProvider> {
-    recv_message_map: Arc<DashMap<&'static str, Vec<(ConnectionId, Vec<u8>)>>>,
+    pub(crate) recv_message_map: Arc<DashMap<&'static str, Vec<(ConnectionId, Vec<u8>)>>>,
+    local_message_map: Arc<DashMap<&'static str, Vec<(ConnectionId, Box<dyn Any + Send + Sync>)>>>,
     established_connections: Arc<DashMap<ConnectionId, Connection>>,
-    new_connections: AsyncChannel<NP::Socket>,
+    /// New sockets, true for those accepted by [`Network::listen`]
+    new_connections: AsyncChannel<(NP::Socket, bool)>,
+    /// Connections without a socket, made by [`Network::connect_local`] or a replay
+    new_virtual_connections: AsyncChannel<ConnectionId>,
+    local_peers: Arc<DashMap<ConnectionId, LocalPeer>>,
     disconnected_connections: AsyncChannel<ConnectionId>,
+    /// Connections closed by [`Network::disconnect`] or [`Network::stop`] whose [`NetworkEvent::Disconnected`](crate::NetworkEvent::Disconnected) wasn't sent yet
+    closed_connections: DashSet<ConnectionId>,
+    /// True if the last connection that went away was lost, rather than closed by [`Network::disconnect`] or [`Network::stop`]
+    pub(crate) connection_lost: AtomicBool,
     error_channel: AsyncChannel<NetworkError>,
+    outgoing_messages: AsyncChannel<network_writer::OutgoingMessage>,
     server_handle: Option<Box<dyn JoinHandle>>,
     connection_tasks: Arc<DashMap<u32, Box<dyn JoinHandle>>>,
     connection_task_counts: AtomicU32,
-    connection_count: u32,
+    connection_count: AtomicU32,
+    entity_connections: Arc<DashMap<Entity, ConnectionId>>,
+    pub(crate) spawn_connection_entities: bool,
+    pub(crate) schedules: EventworkSchedules,
+    pub(crate) recorder: Arc<TrafficRecorder>,
+    pub(crate) authenticator: Option<Authenticator>,
+    pending_connections: Arc<DashMap<ConnectionId, PendingConnection>>,
+    authenticated_connections: AsyncChannel<(ConnectionId, Result<(), NetworkError>)>,
+    pub(crate) rate_limits: SharedRateLimits,
+    /// Connections to disconnect, because they exceeded their rate limits
+    kicked_connections: AsyncChannel<ConnectionId>,
+}
+
+/// A connection waiting for its authenticator, see [`crate::authentication`]
+struct PendingConnection {
+    connection: Connection,
+    /// When the connection is dropped if the authenticator hasn't finished, in the time of the runtime's [`Clock`](crate::Clock)
+    deadline: Duration,
+    /// Lets the connection deliver its messages once it is established
+    ready: Sender<()>,
+}
+
+/// One end of the in-process connection made by [`Network::connect_local`]
+#[derive(Clone, Copy)]
+struct LocalPeer {
+    /// The id messages sent to this end are received from
+    peer: ConnectionId,
+    /// True for the end the local client uses to reach the server
+    is_server: bool,
 }
 
 /// A trait used to drive the network. This is responsible
@@ -63,6 +121,14 @@ pub trait NetworkProvider: 'static + Send + Sync {
     /// The output type of [`Self::accept_loop`]
     type AcceptStream: Stream<Item = Self::Socket> + Unpin + Send;
 
+    /// True if every packet arrives exactly once and in order, like with tcp.
+    /// Providers for unreliable protocols should set this to false.
+    const RELIABLE: bool = true;
+
+    /// True for [`Labelled`](crate::labelled::Labelled) providers, whose network sends [`LabelledEvent`](crate::labelled::LabelledEvent)s
+    /// and [`LabelledData`](crate::labelled::LabelledData) instead of [`NetworkEvent`](crate::NetworkEvent)s and [`NetworkData`](crate::NetworkData).
+    const LABELLED: bool = false;
+
     /// This will be spawned as a background operation to continuously add new connections.
     async fn accept_loop(
         accept_info: Self::AcceptInfo,
@@ -92,4 +158,9 @@ pub trait NetworkProvider: 'static + Send + Sync {
     /// Split the socket into a read and write half, so that the two actions
     /// can be handled concurrently.
     fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf);
+
+    /// The address of the remote end of the socket, if the protocol has one.
+    fn peer_addr(_socket: &Self::Socket) -> Option<SocketAddr> {
+        None
+    }
 }
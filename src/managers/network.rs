@@ -1,21 +1,36 @@
-use std::sync::{
-    atomic::{AtomicU32, Ordering},
-    Arc,
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
 };
 
-use async_channel::unbounded;
-use bevy::prelude::*;
-use dashmap::DashMap;
-use futures_lite::StreamExt;
+use async_channel::{bounded, unbounded, Sender};
+use bevy::{prelude::*, utils::Instant};
+use dashmap::{DashMap, DashSet};
+use futures_lite::{future, StreamExt};
 
 use crate::{
+    admission::AdmissionPolicy,
+    authentication::AuthSession,
+    connection::{ConnectionMetadata, ConnectionStats, ConnectionTarget, NetworkConnection},
     error::NetworkError,
+    labelled::{add_data_event, NetworkDataWriter, NetworkEventWriter},
     network_message::NetworkMessage,
+    permissions::MessagePermissions,
+    rate_limit::{Admission, RateLimiter, SharedRateLimits},
+    recording::{TrafficEvent, TrafficRecorder},
     runtime::{run_async, EventworkRuntime},
-    AsyncChannel, Connection, ConnectionId, NetworkData, NetworkEvent, NetworkPacket, Runtime,
+    AsyncChannel, Connection, ConnectionId, EventworkSchedules, EventworkSet, NetworkData,
+    NetworkEvent, NetworkPacket, Runtime,
 };
 
-use super::{Network, NetworkProvider};
+use super::{LocalPeer, Network, NetworkProvider, PendingConnection};
+
+/// How many received packets of a connection are buffered before it isn't read from anymore, until they are handled.
+/// Keeps a peer from filling up memory while it is authenticating, when nothing but the authenticator reads its packets.
+const INCOMING_PACKET_BUFFER: usize = 64;
 
 impl<NP: NetworkProvider> std::fmt::Debug for Network<NP> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -31,21 +46,144 @@ impl<NP: NetworkProvider> Network<NP> {
     pub(crate) fn new(_provider: NP) -> Self {
         Self {
             recv_message_map: Arc::new(DashMap::new()),
+            local_message_map: Arc::new(DashMap::new()),
             established_connections: Arc::new(DashMap::new()),
             new_connections: AsyncChannel::new(),
+            new_virtual_connections: AsyncChannel::new(),
+            local_peers: Arc::new(DashMap::new()),
             disconnected_connections: AsyncChannel::new(),
+            closed_connections: DashSet::new(),
+            connection_lost: AtomicBool::new(false),
             error_channel: AsyncChannel::new(),
+            outgoing_messages: AsyncChannel::new(),
             server_handle: None,
             connection_tasks: Arc::new(DashMap::new()),
             connection_task_counts: AtomicU32::new(0),
-            connection_count: 0,
+            connection_count: AtomicU32::new(0),
+            entity_connections: Arc::new(DashMap::new()),
+            spawn_connection_entities: false,
+            schedules: EventworkSchedules::default(),
+            authenticator: None,
+            pending_connections: Arc::new(DashMap::new()),
+            authenticated_connections: AsyncChannel::new(),
+            rate_limits: SharedRateLimits::default(),
+            kicked_connections: AsyncChannel::new(),
+            recorder: Arc::new(TrafficRecorder::default()),
         }
     }
 
+    /// The entity spawned for a connection, see [`EventworkPlugin::with_connection_entities`](crate::EventworkPlugin::with_connection_entities)
+    pub fn connection_entity(&self, conn_id: ConnectionId) -> Option<Entity> {
+        self.established_connections
+            .get(&conn_id)
+            .and_then(|connection| connection.entity)
+    }
+
+    /// The connection an entity was spawned for, see [`EventworkPlugin::with_connection_entities`](crate::EventworkPlugin::with_connection_entities)
+    pub fn connection_of_entity(&self, entity: Entity) -> Option<ConnectionId> {
+        self.entity_connections.get(&entity).map(|conn_id| *conn_id)
+    }
+
+    /// Information about an established connection
+    pub fn connection_metadata(&self, conn_id: ConnectionId) -> Option<ConnectionMetadata> {
+        self.established_connections
+            .get(&conn_id)
+            .map(|connection| connection.metadata.clone())
+    }
+
+    /// Live traffic counters of an established connection
+    pub fn connection_stats(&self, conn_id: ConnectionId) -> Option<Arc<ConnectionStats>> {
+        self.established_connections
+            .get(&conn_id)
+            .map(|connection| connection.stats.clone())
+    }
+
+    /// Returns true if the connection is established on this network
+    pub fn contains_connection(&self, conn_id: ConnectionId) -> bool {
+        self.established_connections.contains_key(&conn_id)
+    }
+
+    /// The ids of all established connections
+    ///
+    /// This includes the local client of [`Network::connect_local`], but not the [`Network::local_server`] it talks to.
+    pub fn connection_ids(&self) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.established_connections
+            .iter()
+            .map(|conn| *conn.key())
+            .filter(|conn_id| !self.is_local_server(*conn_id))
+    }
+
+    /// The id of the local client made by [`Network::connect_local`], as seen by the server
+    pub fn local_client(&self) -> Option<ConnectionId> {
+        self.local_peers
+            .iter()
+            .find(|local| !local.is_server)
+            .map(|local| *local.key())
+    }
+
+    /// The id the local client made by [`Network::connect_local`] uses to reach the server
+    pub fn local_server(&self) -> Option<ConnectionId> {
+        self.local_peers
+            .iter()
+            .find(|local| local.is_server)
+            .map(|local| *local.key())
+    }
+
+    /// Returns true if the connection is either end of the connection made by [`Network::connect_local`]
+    pub fn is_local(&self, conn_id: ConnectionId) -> bool {
+        self.local_peers.contains_key(&conn_id)
+    }
+
+    fn is_local_server(&self, conn_id: ConnectionId) -> bool {
+        self.local_peers
+            .get(&conn_id)
+            .is_some_and(|local| local.is_server)
+    }
+
+    /// The amount of established and pending connections, without the local client
+    pub(crate) fn remote_connection_count(&self) -> usize {
+        self.established_connections
+            .iter()
+            .filter(|conn| !self.is_local(*conn.key()))
+            .count()
+            + self.pending_connections.len()
+    }
+
+    /// The amount of established and pending connections with a peer at the address
+    pub(crate) fn connection_count_from(&self, ip: IpAddr) -> usize {
+        let from_ip = |metadata: &ConnectionMetadata| {
+            metadata
+                .peer_addr
+                .is_some_and(|peer_addr| peer_addr.ip().to_canonical() == ip.to_canonical())
+        };
+        self.established_connections
+            .iter()
+            .filter(|conn| from_ip(&conn.metadata))
+            .count()
+            + self
+                .pending_connections
+                .iter()
+                .filter(|pending| from_ip(&pending.connection.metadata))
+                .count()
+    }
+
     /// Returns true if there are any active connections
     #[inline(always)]
     pub fn has_connections(&self) -> bool {
-        self.established_connections.len() > 0
+        !self.established_connections.is_empty()
+    }
+
+    /// Returns true while a connection started with [`Network::connect`] isn't established yet
+    pub fn is_connecting(&self) -> bool {
+        !self.connection_tasks.is_empty()
+            || !self.new_connections.receiver.is_empty()
+            || !self.pending_connections.is_empty()
+    }
+
+    /// Returns true if this network is listening for new clients
+    #[inline(always)]
+    pub fn is_listening(&self) -> bool {
+        self.server_handle.is_some()
     }
 
     /// Start listening for new clients
@@ -73,7 +211,7 @@ impl<NP: NetworkProvider> Network<NP> {
                     Ok(mut listen_stream) => {
                         while let Some(connection) = listen_stream.next().await {
                             new_connections
-                                .send(connection)
+                                .send((connection, true))
                                 .await
                                 .expect("Connection channel has closed");
                         }
@@ -112,7 +250,7 @@ impl<NP: NetworkProvider> Network<NP> {
                 async move {
                     match NP::connect_task(connect_info, settings).await {
                         Ok(connection) => connection_event_sender
-                            .send(connection)
+                            .send((connection, false))
                             .await
                             .expect("Connection channel has closed"),
                         Err(e) => network_error_sender
@@ -132,12 +270,116 @@ impl<NP: NetworkProvider> Network<NP> {
         );
     }
 
-    /// Send a message to a specific client
+    /// Connect this app to itself, for a listen server whose host also plays.
+    ///
+    /// The local client is a client like any other, with its own [`ConnectionId`] and a [`NetworkEvent::Connected`].
+    /// Messages sent to it are received by this app as [`NetworkData`] from [`Network::local_server`],
+    /// and messages sent to [`Network::local_server`] are received as [`NetworkData`] from the local client,
+    /// so the same systems handle the host and remote players.
+    ///
+    /// The messages never touch a socket, and those sent with [`Network::send_message`], [`Network::broadcast`]
+    /// or a [`NetworkWriter`](super::network_writer::NetworkWriter) aren't even serialized.
+    /// Replicated state isn't sent to the local client, since the host already has it.
+    ///
+    /// Returns the id of the local client, or the existing one if this app already is connected to itself.
+    pub fn connect_local<RT: Runtime>(&self, runtime: &RT) -> ConnectionId {
+        if let Some(client) = self.local_client() {
+            return client;
+        }
+
+        debug!("Connecting to ourselves");
+
+        let client = ConnectionId {
+            id: self.connection_count.fetch_add(1, Ordering::Relaxed),
+        };
+        let server = ConnectionId {
+            id: self.connection_count.fetch_add(1, Ordering::Relaxed),
+        };
+
+        for (conn_id, peer, is_server) in [(client, server, false), (server, client, true)] {
+            // Requests and responses are sent as packets, pass those on without a socket
+            let (outgoing_tx, outgoing_rx) = unbounded();
+            let stats = Arc::new(ConnectionStats::default());
+            let send_stats = stats.clone();
+            let recv_message_map = self.recv_message_map.clone();
+            let recorder = self.recorder.clone();
+            let route_task = run_async(
+                async move {
+                    while let Ok(packet) = outgoing_rx.recv().await {
+                        send_stats.record_sent(&packet);
+                        recorder.record_packet(conn_id, &packet, TrafficEvent::Sent);
+                        match recv_message_map.get_mut(&packet.kind[..]) {
+                            Some(mut packets) => packets.push((peer, packet.data)),
+                            None => {
+                                error!(
+                                    "Could not find existing entries for message kinds: {:?}",
+                                    packet
+                                );
+                            }
+                        }
+                    }
+                },
+                runtime,
+            );
+
+            self.local_peers
+                .insert(conn_id, LocalPeer { peer, is_server });
+            self.established_connections.insert(
+                conn_id,
+                Connection {
+                    tasks: vec![Box::new(route_task)],
+                    send_message: outgoing_tx,
+                    metadata: ConnectionMetadata {
+                        peer_addr: None,
+                        connected_at: Instant::now(),
+                    },
+                    stats,
+                    entity: None,
+                },
+            );
+        }
+
+        // The receiver lives as long as the network, so this can't fail
+        let _ = self.new_virtual_connections.sender.try_send(client);
+
+        client
+    }
+
+    /// Establish a connection without a socket for a replay, messages sent to it end up in `send_message`
+    pub(crate) fn add_replayed_connection(
+        &self,
+        conn_id: ConnectionId,
+        send_message: Sender<NetworkPacket>,
+    ) {
+        self.established_connections.insert(
+            conn_id,
+            Connection {
+                tasks: Vec::new(),
+                send_message,
+                metadata: ConnectionMetadata {
+                    peer_addr: None,
+                    connected_at: Instant::now(),
+                },
+                stats: Arc::new(ConnectionStats::default()),
+                entity: None,
+            },
+        );
+        // The receiver lives as long as the network, so this can't fail
+        let _ = self.new_virtual_connections.sender.try_send(conn_id);
+    }
+
+    /// Send a message to a specific client, addressed by its [`ConnectionId`] or the [`Entity`] of its [`NetworkConnection`]
     pub fn send_message<T: NetworkMessage>(
         &self,
-        client_id: ConnectionId,
+        client: impl ConnectionTarget,
         message: T,
     ) -> Result<(), NetworkError> {
+        let client_id = client.connection_id(self)?;
+        if let Some(source) = self.local_source(client_id) {
+            self.send_local(source, message);
+            return Ok(());
+        }
+
         let connection = match self.established_connections.get(&client_id) {
             Some(conn) => conn,
             None => return Err(NetworkError::ConnectionNotFound(client_id)),
@@ -161,8 +403,33 @@ impl<NP: NetworkProvider> Network<NP> {
 
     /// Broadcast a message to all connected clients
     pub fn broadcast<T: NetworkMessage + Clone>(&self, message: T) {
-        let serialized_message = bincode::serialize(&message).expect("Couldn't serialize message!");
-        for connection in self.established_connections.iter() {
+        self.broadcast_filtered(message, |_| true);
+    }
+
+    /// Broadcast a message to all connected clients the filter returns true for
+    ///
+    /// The message is only serialized once, no matter how many clients it is sent to.
+    pub fn broadcast_filtered<T: NetworkMessage + Clone>(
+        &self,
+        message: T,
+        mut filter: impl FnMut(&ConnectionId) -> bool,
+    ) {
+        let serialized_message = match bincode::serialize(&message) {
+            Ok(serialized_message) => serialized_message,
+            Err(err) => {
+                error!("Could not serialize {}: {}", T::NAME, err);
+                let _ = self
+                    .error_channel
+                    .sender
+                    .try_send(NetworkError::Serialization);
+                return;
+            }
+        };
+        for connection in self
+            .established_connections
+            .iter()
+            .filter(|conn| !self.is_local(*conn.key()) && filter(conn.key()))
+        {
             let packet = NetworkPacket {
                 kind: String::from(T::NAME),
                 data: serialized_message.clone(),
@@ -175,6 +442,49 @@ impl<NP: NetworkProvider> Network<NP> {
                 }
             }
         }
+
+        if let (Some(client), Some(server)) = (self.local_client(), self.local_server()) {
+            if filter(&client) {
+                self.send_local(server, message);
+            }
+        }
+    }
+
+    /// Like [`Network::send_message`], but skips the local client of [`Network::connect_local`]
+    pub(crate) fn send_remote<T: NetworkMessage>(
+        &self,
+        client: ConnectionId,
+        message: T,
+    ) -> Result<(), NetworkError> {
+        if self.is_local(client) {
+            return Ok(());
+        }
+        self.send_message(client, message)
+    }
+
+    /// Like [`Network::broadcast`], but skips the local client of [`Network::connect_local`]
+    pub(crate) fn broadcast_remote<T: NetworkMessage + Clone>(&self, message: T) {
+        self.broadcast_filtered(message, |conn_id| !self.is_local(*conn_id));
+    }
+
+    /// The id a message sent to the connection is received from, if it is one of the local ones
+    pub(crate) fn local_source(&self, conn_id: ConnectionId) -> Option<ConnectionId> {
+        self.local_peers.get(&conn_id).map(|local| local.peer)
+    }
+
+    /// Hand a message to the local end of [`Network::connect_local`] as is, without serializing it
+    pub(crate) fn send_local<T: NetworkMessage>(&self, source: ConnectionId, message: T) {
+        if !self.recv_message_map.contains_key(T::NAME) {
+            error!(
+                "Could not find existing entries for message kinds: {}",
+                T::NAME
+            );
+            return;
+        }
+        self.local_message_map
+            .entry(T::NAME)
+            .or_default()
+            .push((source, Box::new(message)));
     }
 
     /// Disconnect all clients and stop listening for new ones
@@ -184,13 +494,16 @@ impl<NP: NetworkProvider> Network<NP> {
     pub fn stop(&mut self) {
         if let Some(mut conn) = self.server_handle.take() {
             conn.abort();
-            for conn in self.established_connections.iter() {
-                match self.disconnected_connections.sender.try_send(*conn.key()) {
+            for conn_id in self.connection_ids() {
+                self.closed_connections.insert(conn_id);
+                match self.disconnected_connections.sender.try_send(conn_id) {
                     Ok(_) => (),
                     Err(err) => warn!("Could not send to client because: {}", err),
                 }
             }
             self.established_connections.clear();
+            self.pending_connections.clear();
+            self.local_peers.clear();
             self.recv_message_map.clear();
 
             while self.new_connections.receiver.try_recv().is_ok() {}
@@ -199,6 +512,23 @@ impl<NP: NetworkProvider> Network<NP> {
 
     /// Disconnect a specific client
     pub fn disconnect(&self, conn_id: ConnectionId) -> Result<(), NetworkError> {
+        if let Some((_, local)) = self.local_peers.remove(&conn_id) {
+            // Both ends of the local connection go away together
+            self.local_peers.remove(&local.peer);
+            for conn_id in [conn_id, local.peer] {
+                if let Some((_, connection)) = self.established_connections.remove(&conn_id) {
+                    connection.stop();
+                }
+            }
+
+            let client = if local.is_server { local.peer } else { conn_id };
+            self.closed_connections.insert(client);
+            if let Err(err) = self.disconnected_connections.sender.try_send(client) {
+                warn!("Could not report disconnect because: {}", err);
+            }
+            return Ok(());
+        }
+
         let connection = if let Some(conn) = self.established_connections.remove(&conn_id) {
             conn
         } else {
@@ -207,36 +537,76 @@ impl<NP: NetworkProvider> Network<NP> {
 
         connection.1.stop();
 
+        // The receive task was stopped, so report the disconnect ourselves
+        self.closed_connections.insert(conn_id);
+        if let Err(err) = self.disconnected_connections.sender.try_send(conn_id) {
+            warn!("Could not report disconnect because: {}", err);
+        }
+
         Ok(())
     }
 }
 
 pub(crate) fn handle_new_incoming_connections<NP: NetworkProvider, RT: Runtime>(
-    mut server: ResMut<Network<NP>>,
+    server: Res<Network<NP>>,
     runtime: Res<EventworkRuntime<RT>>,
     network_settings: Res<NP::NetworkSettings>,
-    mut network_events: EventWriter<NetworkEvent>,
+    admission: Option<Res<AdmissionPolicy<NP>>>,
+    mut network_events: NetworkEventWriter<NP>,
+    mut commands: Commands,
 ) {
-    while let Ok(new_conn) = server.new_connections.receiver.try_recv() {
-        let id = server.connection_count;
+    while let Ok((new_conn, accepted)) = server.new_connections.receiver.try_recv() {
+        let peer_addr = NP::peer_addr(&new_conn);
+        if let Some(admission) = admission.as_ref().filter(|_| accepted) {
+            if let Err(reason) = admission.admit(&server, peer_addr) {
+                debug!("Rejected a new connection from {:?}: {}", peer_addr, reason);
+                // Closes the socket before anything was exchanged over it
+                drop(new_conn);
+                network_events.send(NetworkEvent::Error(NetworkError::Rejected(
+                    peer_addr, reason,
+                )));
+                continue;
+            }
+        }
+
+        let id = server.connection_count.fetch_add(1, Ordering::Relaxed);
         let conn_id = ConnectionId { id };
-        server.connection_count += 1;
+
+        let metadata = ConnectionMetadata {
+            peer_addr,
+            connected_at: Instant::now(),
+        };
+        let stats = Arc::new(ConnectionStats::default());
+        let receive_stats = stats.clone();
+        let send_stats = stats.clone();
 
         let (read_half, write_half) = NP::split(new_conn);
         let recv_message_map = server.recv_message_map.clone();
         let read_network_settings = network_settings.clone();
         let write_network_settings = network_settings.clone();
         let disconnected_connections = server.disconnected_connections.sender.clone();
+        let recorder = server.recorder.clone();
+        let mut rate_limiter = RateLimiter::new(
+            conn_id,
+            stats.clone(),
+            server.error_channel.sender.clone(),
+            server.kicked_connections.sender.clone(),
+            server.rate_limits.clone(),
+            runtime.clock(),
+        );
 
         let (outgoing_tx, outgoing_rx) = unbounded();
-        let (incoming_tx, incoming_rx) = unbounded();
+        let (received_tx, received_rx) = bounded(INCOMING_PACKET_BUFFER);
+        let (incoming_tx, incoming_rx) = bounded(INCOMING_PACKET_BUFFER);
+        let (ready_tx, ready_rx) = bounded::<()>(1);
+        let session_incoming = incoming_rx.clone();
 
-        server.established_connections.insert(
-                conn_id,
-                Connection {
-                    receive_task: Box::new(run_async(async move {
+        let mut connection = Connection {
+            tasks: vec![
+                Box::new(run_async(
+                    async move {
                         trace!("Starting listen task for {}", id);
-                        NP::recv_loop(read_half, incoming_tx, read_network_settings).await;
+                        NP::recv_loop(read_half, received_tx, read_network_settings).await;
 
                         match disconnected_connections.send(conn_id).await {
                             Ok(_) => (),
@@ -244,35 +614,241 @@ pub(crate) fn handle_new_incoming_connections<NP: NetworkProvider, RT: Runtime>(
                                 error!("Could not send disconnected event, because channel is disconnected");
                             }
                         }
-                    }, &runtime.0)),
-                    map_receive_task: Box::new(run_async(async move{
-                        while let Ok(packet) = incoming_rx.recv().await{
+                    },
+                    &runtime.0,
+                )),
+                Box::new(run_async(
+                    async move {
+                        // Limited before they are queued, so the authenticator only gets packets within the limits too
+                        while let Ok(packet) = received_rx.recv().await {
+                            receive_stats.record_received(&packet);
+                            match rate_limiter.admit(&packet.kind) {
+                                Admission::Accept => (),
+                                Admission::Drop => continue,
+                                Admission::Disconnect => break,
+                            }
+                            if incoming_tx.send(packet).await.is_err() {
+                                break;
+                            }
+                        }
+                    },
+                    &runtime.0,
+                )),
+                Box::new(run_async(
+                    async move {
+                        // Packets are left to the authenticator until the connection is established
+                        if ready_rx.recv().await.is_err() {
+                            return;
+                        }
+                        while let Ok(packet) = incoming_rx.recv().await {
                             match recv_message_map.get_mut(&packet.kind[..]) {
                                 Some(mut packets) => packets.push((conn_id, packet.data)),
                                 None => {
-                                    error!("Could not find existing entries for message kinds: {:?}", packet);
+                                    error!(
+                                        "Could not find existing entries for message kinds: {:?}",
+                                        packet
+                                    );
                                 }
                             }
                         }
-                    }, &runtime.0)),
-                    send_task: Box::new(run_async(async move {
+                    },
+                    &runtime.0,
+                )),
+                Box::new(run_async(
+                    async move {
                         trace!("Starting send task for {}", id);
-                        NP::send_loop(write_half, outgoing_rx, write_network_settings).await;
-                    }, &runtime.0)),
-                    send_message: outgoing_tx,
-                    //addr: new_conn.addr,
-                },
-            );
+                        let (counted_tx, counted_rx) = unbounded();
+                        let count_sent = async move {
+                            while let Ok(packet) = outgoing_rx.recv().await {
+                                send_stats.record_sent(&packet);
+                                recorder.record_packet(conn_id, &packet, TrafficEvent::Sent);
+                                if counted_tx.send(packet).await.is_err() {
+                                    break;
+                                }
+                            }
+                        };
+                        future::zip(
+                            count_sent,
+                            NP::send_loop(write_half, counted_rx, write_network_settings),
+                        )
+                        .await;
+                    },
+                    &runtime.0,
+                )),
+            ],
+            send_message: outgoing_tx,
+            metadata,
+            stats,
+            entity: None,
+        };
+
+        match &server.authenticator {
+            Some(authenticator) => {
+                let session = AuthSession {
+                    conn_id,
+                    peer_addr: connection.metadata.peer_addr,
+                    incoming: session_incoming,
+                    outgoing: connection.send_message.clone(),
+                };
+                let authentication = (authenticator.authenticate)(session);
+                let authenticated_connections = server.authenticated_connections.sender.clone();
+                connection.tasks.push(Box::new(run_async(
+                    async move {
+                        let result = authentication.await;
+                        // Fails if the network was dropped, then nobody is waiting for the result anymore
+                        let _ = authenticated_connections.send((conn_id, result)).await;
+                    },
+                    &runtime.0,
+                )));
 
-        network_events.send(NetworkEvent::Connected(conn_id));
+                debug!("Authenticating {}", conn_id);
+                server.pending_connections.insert(
+                    conn_id,
+                    PendingConnection {
+                        connection,
+                        deadline: runtime.clock().now() + authenticator.timeout,
+                        ready: ready_tx,
+                    },
+                );
+            }
+            None => {
+                // The receiver is owned by a task of the connection, so this can't fail
+                let _ = ready_tx.try_send(());
+                server.established_connections.insert(conn_id, connection);
+                announce_connection(&server, conn_id, &mut commands, &mut network_events);
+            }
+        }
+    }
+
+    while let Ok((conn_id, result)) = server.authenticated_connections.receiver.try_recv() {
+        let Some((_, pending)) = server.pending_connections.remove(&conn_id) else {
+            // Already timed out
+            continue;
+        };
+        match result {
+            Ok(()) => {
+                debug!("Authenticated {}", conn_id);
+                // The receiver is owned by a task of the connection, so this can't fail
+                let _ = pending.ready.try_send(());
+                server
+                    .established_connections
+                    .insert(conn_id, pending.connection);
+                announce_connection(&server, conn_id, &mut commands, &mut network_events);
+            }
+            Err(err) => {
+                pending.connection.stop();
+                network_events.send(NetworkEvent::Error(NetworkError::Authentication(
+                    conn_id,
+                    err.to_string(),
+                )));
+            }
+        }
+    }
+
+    let now = runtime.clock().now();
+    let timed_out: Vec<ConnectionId> = server
+        .pending_connections
+        .iter()
+        .filter(|pending| pending.deadline <= now)
+        .map(|pending| *pending.key())
+        .collect();
+    for conn_id in timed_out {
+        if let Some((_, pending)) = server.pending_connections.remove(&conn_id) {
+            pending.connection.stop();
+            network_events.send(NetworkEvent::Error(NetworkError::Authentication(
+                conn_id,
+                String::from("Authentication timed out"),
+            )));
+        }
+    }
+
+    while let Ok(conn_id) = server.new_virtual_connections.receiver.try_recv() {
+        if !server.established_connections.contains_key(&conn_id) {
+            // Disconnected again before we got to it
+            continue;
+        }
+        announce_connection(&server, conn_id, &mut commands, &mut network_events);
+    }
+
+    while let Ok(conn_id) = server.kicked_connections.receiver.try_recv() {
+        if let Some((_, pending)) = server.pending_connections.remove(&conn_id) {
+            pending.connection.stop();
+            network_events.send(NetworkEvent::Error(NetworkError::Authentication(
+                conn_id,
+                String::from("Rate limited during authentication"),
+            )));
+            continue;
+        }
+        // Fails if it was disconnected in the meantime
+        let _ = server.disconnect(conn_id);
     }
 
     while let Ok(disconnected_connection) = server.disconnected_connections.receiver.try_recv() {
-        server
+        if let Some((_, pending)) = server.pending_connections.remove(&disconnected_connection) {
+            pending.connection.stop();
+            network_events.send(NetworkEvent::Error(NetworkError::Authentication(
+                disconnected_connection,
+                String::from("Disconnected during authentication"),
+            )));
+            continue;
+        }
+
+        let lost = server
             .established_connections
-            .remove(&disconnected_connection);
+            .remove(&disconnected_connection)
+            .is_some();
+        let closed = !lost
+            && server
+                .closed_connections
+                .remove(&disconnected_connection)
+                .is_some();
+        if !lost && !closed {
+            // Reported by the receive task and by a disconnect at the same time, or lost during authentication
+            continue;
+        }
+        server.connection_lost.store(lost, Ordering::Relaxed);
+        server.entity_connections.retain(|entity, conn_id| {
+            if *conn_id == disconnected_connection {
+                commands.entity(*entity).despawn();
+                false
+            } else {
+                true
+            }
+        });
+        server
+            .recorder
+            .record(disconnected_connection, TrafficEvent::Disconnected);
         network_events.send(NetworkEvent::Disconnected(disconnected_connection));
     }
+
+    while let Ok(err) = server.error_channel.receiver.try_recv() {
+        network_events.send(NetworkEvent::Error(err));
+    }
+}
+
+/// Spawns the entity of a newly established connection and announces it with a [`NetworkEvent::Connected`]
+fn announce_connection<NP: NetworkProvider>(
+    server: &Network<NP>,
+    conn_id: ConnectionId,
+    commands: &mut Commands,
+    network_events: &mut NetworkEventWriter<NP>,
+) {
+    if server.spawn_connection_entities {
+        if let Some(mut connection) = server.established_connections.get_mut(&conn_id) {
+            let entity = commands
+                .spawn(NetworkConnection {
+                    id: conn_id,
+                    metadata: connection.metadata.clone(),
+                    stats: connection.stats.clone(),
+                })
+                .id();
+            server.entity_connections.insert(entity, conn_id);
+            connection.entity = Some(entity);
+        }
+    }
+
+    server.recorder.record(conn_id, TrafficEvent::Connected);
+    network_events.send(NetworkEvent::Connected(conn_id));
 }
 
 /// A utility trait on [`App`] to easily register [`NetworkMessage`]s
@@ -299,14 +875,19 @@ impl AppNetworkMessage for App {
             T::NAME
         );
         server.recv_message_map.insert(T::NAME, Vec::new());
-        self.add_event::<NetworkData<T>>();
-        self.add_systems(PreUpdate, register_message::<T, NP>)
+        let schedules = server.schedules;
+        add_data_event::<T, NP>(self);
+        self.add_systems(
+            schedules.receive,
+            register_message::<T, NP>.in_set(EventworkSet::Receive),
+        )
     }
 }
 
 pub(crate) fn register_message<T, NP: NetworkProvider>(
     net_res: ResMut<Network<NP>>,
-    mut events: EventWriter<NetworkData<T>>,
+    permissions: Option<Res<MessagePermissions<NP>>>,
+    mut events: NetworkDataWriter<T, NP>,
 ) where
     T: NetworkMessage,
 {
@@ -315,9 +896,194 @@ pub(crate) fn register_message<T, NP: NetworkProvider>(
         None => return,
     };
 
+    let mut violators = Vec::new();
+    if let Some(permissions) = &permissions {
+        messages
+            .retain(|(source, _)| permissions.check(&net_res, *source, T::NAME, &mut violators));
+    }
+
+    if net_res.recorder.is_active() {
+        for (source, msg) in messages.iter() {
+            net_res.recorder.record(
+                *source,
+                TrafficEvent::Received(NetworkPacket {
+                    kind: String::from(T::NAME),
+                    data: msg.clone(),
+                }),
+            );
+        }
+    }
+
     events.send_batch(messages.drain(..).filter_map(|(source, msg)| {
         bincode::deserialize::<T>(&msg)
             .ok()
             .map(|inner| NetworkData { source, inner })
     }));
+    drop(messages);
+
+    if let Some(mut messages) = net_res.local_message_map.get_mut(T::NAME) {
+        if let Some(permissions) = &permissions {
+            messages.retain(|(source, _)| {
+                permissions.check(&net_res, *source, T::NAME, &mut violators)
+            });
+        }
+        events.send_batch(messages.drain(..).filter_map(|(source, msg)| {
+            msg.downcast::<T>().ok().map(|inner| NetworkData {
+                source,
+                inner: *inner,
+            })
+        }));
+    }
+
+    // Not while the messages are borrowed, disconnecting locks the maps of the connection
+    for conn_id in violators {
+        // Fails if it was already disconnected
+        let _ = net_res.disconnect(conn_id);
+    }
+}
+
+#[cfg(all(test, feature = "tcp"))]
+mod tests {
+    use std::{future, io::Write, net::TcpListener, time::Duration};
+
+    use bevy::ecs::event::{Events, ManualEventReader};
+
+    use super::*;
+    use crate::{
+        authentication::{AppNetworkAuthentication, AuthSession},
+        rate_limit::{AppNetworkRateLimits, RateLimit, RateLimitPolicy, RateLimits},
+        tcp::{NetworkSettings, TcpProvider},
+        EventworkPlugin, SimulatedRuntime,
+    };
+
+    fn authentication_failed(
+        app: &mut App,
+        reader: &mut ManualEventReader<NetworkEvent>,
+        expected: &str,
+    ) -> bool {
+        let events = app.world().resource::<Events<NetworkEvent>>();
+        reader.read(events).any(|event| {
+            matches!(event, NetworkEvent::Error(NetworkError::Authentication(_, reason)) if reason == expected)
+        })
+    }
+
+    /// An app authenticating its connections with an authenticator that never finishes, listening on a free port
+    fn listen_without_finishing_authentication(
+        runtime: &SimulatedRuntime,
+    ) -> (App, std::net::SocketAddr) {
+        let mut app = App::new();
+        app.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+        app.insert_resource(NetworkSettings::default());
+        app.insert_resource(EventworkRuntime(runtime.clone()));
+        app.authenticate_connections::<TcpProvider, _>(
+            Duration::from_secs(5),
+            |_session: AuthSession| future::pending(),
+        );
+
+        // A free port, for the network to listen on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("No free port");
+        app.world_mut()
+            .resource_scope(|world, mut net: Mut<Network<TcpProvider>>| {
+                net.listen(addr, runtime, world.resource::<NetworkSettings>())
+            })
+            .expect("Failed to listen");
+        // Binds the listener
+        runtime.run_until_stalled();
+        (app, addr)
+    }
+
+    #[test]
+    fn authentication_times_out_with_the_runtime_clock() {
+        let runtime = SimulatedRuntime::new();
+        let (mut app, addr) = listen_without_finishing_authentication(&runtime);
+
+        let _client = std::net::TcpStream::connect(addr).expect("Failed to connect");
+        let mut reader = ManualEventReader::default();
+        for _ in 0..200 {
+            runtime.run_until_stalled();
+            app.update();
+            if !app
+                .world()
+                .resource::<Network<TcpProvider>>()
+                .pending_connections
+                .is_empty()
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(
+            app.world()
+                .resource::<Network<TcpProvider>>()
+                .pending_connections
+                .len(),
+            1
+        );
+
+        runtime.advance(Duration::from_secs(4));
+        app.update();
+        assert!(!authentication_failed(
+            &mut app,
+            &mut reader,
+            "Authentication timed out"
+        ));
+
+        runtime.advance(Duration::from_secs(1));
+        app.update();
+        assert!(authentication_failed(
+            &mut app,
+            &mut reader,
+            "Authentication timed out"
+        ));
+        assert!(app
+            .world()
+            .resource::<Network<TcpProvider>>()
+            .pending_connections
+            .is_empty());
+    }
+
+    #[test]
+    fn rate_limits_apply_during_authentication() {
+        let runtime = SimulatedRuntime::new();
+        let (mut app, addr) = listen_without_finishing_authentication(&runtime);
+        app.limit_rates::<TcpProvider>(
+            RateLimits::new()
+                .global(RateLimit::per_second(1).burst(5))
+                .policy(RateLimitPolicy::Disconnect),
+        );
+
+        // More packets than the authenticator reads, and than are allowed
+        let mut client = std::net::TcpStream::connect(addr).expect("Failed to connect");
+        for _ in 0..INCOMING_PACKET_BUFFER * 2 {
+            let encoded = bincode::serialize(&NetworkPacket {
+                kind: String::from("flood"),
+                data: vec![0; 16],
+            })
+            .expect("Failed to encode");
+            client
+                .write_all(&(encoded.len() as u64).to_le_bytes())
+                .and_then(|_| client.write_all(&encoded))
+                .expect("Failed to send");
+        }
+
+        let mut reader = ManualEventReader::default();
+        let mut kicked = false;
+        for _ in 0..200 {
+            runtime.run_until_stalled();
+            app.update();
+            if authentication_failed(&mut app, &mut reader, "Rate limited during authentication") {
+                kicked = true;
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(kicked);
+        assert!(app
+            .world()
+            .resource::<Network<TcpProvider>>()
+            .pending_connections
+            .is_empty());
+    }
 }
@@ -3,7 +3,8 @@
 //! This documentation assumes you have an app setup correctly that can send and receive normal messages. Please refer to the repository readme
 //! and the [library documentation](https://docs.rs/bevy_eventwork/latest/bevy_eventwork/index.html) for help with that.
 //!
-//! Note that in bevy_eventwork clients and servers use the same plugin architecture but one (the server) will listen for connections but can't connect while listening and the other (the client) will connect to other apps but can't listen when connected.
+//! Note that in bevy_eventwork clients and servers use the same plugin architecture but one (the server) will listen for connections and the other (the client) will connect to other apps.
+//! A listen server can also be a client of itself with [`Network::connect_local`](crate::Network::connect_local), in which case both happen in the same app.
 //! This is important to understand because both apps can request and receive from each other as long as the message type is setup to do so in that app.
 //! In this example the client is making requests to the server but if you flipped or duplicated all the message setup the server could make requests to the client.
 //!
@@ -147,6 +148,62 @@
 //! }
 //! ```
 //!
+//! ### Broadcasting requests
+//!
+//! To ask every connection at once, use [`Requester::broadcast_request`](self::network_request::Requester::broadcast_request) or [`Requester::broadcast_request_filtered`](self::network_request::Requester::broadcast_request_filtered).
+//! Instead of a single [`Response`](self::network_request::Response) you get a [`BroadcastResponse`](self::network_request::BroadcastResponse) that yields a [`BroadcastResult`](self::network_request::BroadcastResult) per connection
+//! as they arrive, and reports completion once every connection has answered, failed or timed out.
+//!
+//! ```rust
+//! # use bevy::prelude::*;
+//! # use bevy_eventwork::{NetworkMessage, tcp::TcpProvider, managers::network_request::*};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct RequestStatus;
+//! # impl RequestMessage for RequestStatus {
+//! #   type ResponseMessage = StatusResponse;
+//! #   const REQUEST_NAME: &'static str = "client_request_status";
+//! # }
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct StatusResponse{
+//! #    pub response: bool
+//! # }
+//! # impl NetworkMessage for StatusResponse {
+//! #    const NAME: &'static str = "client_request_status_response";
+//! # }
+//! use bevy::utils::Duration;
+//!
+//! #[derive(Resource)]
+//! struct StatusBroadcast(BroadcastResponse<StatusResponse>);
+//!
+//! fn ask_everyone(net: Requester<RequestStatus, TcpProvider>, mut commands: Commands) {
+//!     let responses = net
+//!         .broadcast_request(RequestStatus)
+//!         .with_timeout(Duration::from_secs(5));
+//!     commands.insert_resource(StatusBroadcast(responses));
+//! }
+//!
+//! fn poll_broadcast(mut broadcast: ResMut<StatusBroadcast>, mut commands: Commands) {
+//!     for (conn_id, result) in broadcast.0.try_recv() {
+//!         match result {
+//!             BroadcastResult::Response(status) => println!("{}: {}", conn_id, status.response),
+//!             BroadcastResult::Failed(err) => println!("{}: {}", conn_id, err),
+//!             BroadcastResult::TimedOut => println!("{}: timed out", conn_id),
+//!         }
+//!     }
+//!     if broadcast.0.is_complete() {
+//!         commands.remove_resource::<StatusBroadcast>();
+//!     }
+//! }
+//! ```
+//!
+//! ### Cancelling requests
+//!
+//! If the response is no longer needed, for example because the player closed the menu that asked for it, call
+//! [`Response::cancel`](self::network_request::Response::cancel). The remote is notified and
+//! [`Request::is_cancelled`](self::network_request::Request::is_cancelled) starts returning true, so long running handlers can stop early.
+//! Responses to cancelled requests are not sent, and async request handlers are aborted.
+//!
 //! ## Example Server app
 //!
 //! Setting up our server is simple. We just need to register to listen for the *Requests* of our given request
@@ -200,18 +257,117 @@
 //!     }
 //! }
 //! ```
+//!
+//! ### Request handlers
+//!
+//! Instead of reading [`Request`](self::network_request::Request) events and responding by hand, a system can be registered
+//! as the handler of a request type with [`AppNetworkRequestMessage::add_request_handler`](self::network_request::AppNetworkRequestMessage::add_request_handler).
+//! It receives the source and the request as its input and returns the response, which eventwork then sends back itself.
+//!
+//! ```rust
+//! # use bevy::prelude::*;
+//! # use bevy_eventwork::{NetworkMessage, ConnectionId, tcp::TcpProvider, managers::network_request::*};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct RequestStatus;
+//! # impl RequestMessage for RequestStatus {
+//! #   type ResponseMessage = StatusResponse;
+//! #   const REQUEST_NAME: &'static str = "client_request_status";
+//! # }
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct StatusResponse{
+//! #    pub response: bool
+//! # }
+//! # impl NetworkMessage for StatusResponse {
+//! #    const NAME: &'static str = "client_request_status_response";
+//! # }
+//! fn build(app: &mut App) {
+//!     app.add_request_handler::<RequestStatus, TcpProvider, _>(handle_request_status);
+//! }
+//!
+//! fn handle_request_status(
+//!     In((source, _request)): In<(ConnectionId, RequestStatus)>,
+//!     time: Res<Time>,
+//! ) -> StatusResponse {
+//!     info!("{} asked for the status at {:?}", source, time.elapsed());
+//!     StatusResponse { response: true }
+//! }
+//! ```
+//!
+//! Handlers that need to wait on something before answering can be registered with
+//! [`AppNetworkRequestMessage::add_async_request_handler`](self::network_request::AppNetworkRequestMessage::add_async_request_handler).
+//! They run on the [`EventworkRuntime`](crate::EventworkRuntime) and get a clone of a resource of your choice as their context.
+//!
+//! ```rust
+//! # use bevy::prelude::*;
+//! # use bevy::tasks::TaskPool;
+//! # use bevy_eventwork::{NetworkMessage, ConnectionId, tcp::TcpProvider, managers::network_request::*};
+//! # use serde::{Serialize, Deserialize};
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct RequestStatus;
+//! # impl RequestMessage for RequestStatus {
+//! #   type ResponseMessage = StatusResponse;
+//! #   const REQUEST_NAME: &'static str = "client_request_status";
+//! # }
+//! # #[derive(Debug, Serialize, Deserialize, Clone)]
+//! # struct StatusResponse{
+//! #    pub response: bool
+//! # }
+//! # impl NetworkMessage for StatusResponse {
+//! #    const NAME: &'static str = "client_request_status_response";
+//! # }
+//! #[derive(Resource, Clone)]
+//! struct Database;
+//!
+//! impl Database {
+//!     async fn is_healthy(&self) -> bool {
+//!         true
+//!     }
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     app.insert_resource(Database);
+//!     app.add_async_request_handler::<RequestStatus, TcpProvider, TaskPool, Database, _>(
+//!         |_source, _request, database| async move {
+//!             StatusResponse {
+//!                 response: database.is_healthy().await,
+//!             }
+//!         },
+//!     );
+//! }
+//! ```
 
-use std::{fmt::Debug, marker::PhantomData, sync::atomic::AtomicU64};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+};
 
-use async_channel::{Receiver, Sender};
+use async_channel::{Receiver, Sender, TryRecvError};
 use bevy::{
-    ecs::system::SystemParam,
-    prelude::{debug, App, Event, EventReader, EventWriter, PreUpdate, Res, ResMut, Resource},
+    ecs::{event::ManualEventReader, system::SystemParam},
+    prelude::{
+        debug, error, App, Event, EventReader, EventWriter, Events, IntoSystem, IntoSystemConfigs,
+        Mut, Res, ResMut, Resource, System, World,
+    },
+    utils::Duration,
 };
 use dashmap::DashMap;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{error::NetworkError, ConnectionId, NetworkData, NetworkMessage, NetworkPacket};
+use crate::{
+    error::NetworkError,
+    labelled::{add_data_event, send_network_event, NetworkDataReader, NetworkEventReader},
+    runtime::{run_async, JoinHandle},
+    AsyncChannel, Clock, ConnectionId, EventworkRuntime, EventworkSchedules, EventworkSet,
+    NetworkEvent, NetworkMessage, NetworkPacket, Runtime,
+};
 
 use super::{network::register_message, Network, NetworkProvider};
 
@@ -219,7 +375,8 @@ use super::{network::register_message, Network, NetworkProvider};
 /// A wrapper around [`Network`] that allows for the sending of [`RequestMessage`]'s.
 pub struct Requester<'w, 's, T: RequestMessage, NP: NetworkProvider> {
     server: Res<'w, Network<NP>>,
-    response_map: Res<'w, ResponseMap<T>>,
+    response_map: Res<'w, ResponseMap<T, NP>>,
+    clock: Res<'w, Clock>,
     #[system_param(ignore)]
     marker: PhantomData<&'s usize>,
 }
@@ -231,10 +388,56 @@ impl<'w, 's, T: RequestMessage, NP: NetworkProvider> Requester<'w, 's, T, NP> {
         client_id: ConnectionId,
         request: T,
     ) -> Result<Response<T::ResponseMessage>, NetworkError> {
-        let (id, response) = self.response_map.get_responder();
-        self.server
-            .send_message(client_id, RequestInternal { id, request })?;
-        Ok(response)
+        let (id, response) = self.response_map.get_responder(client_id);
+        if let Err(err) = self
+            .server
+            .send_message(client_id, RequestInternal { id, request })
+        {
+            self.response_map.remove(&id);
+            return Err(err);
+        }
+        Ok(Response {
+            canceller: RequestCanceller::new(&self.server, client_id, T::REQUEST_NAME, id),
+            ..response
+        })
+    }
+
+    /// Sends a request to every established connection and returns an object that gathers
+    /// the responses of all of them
+    pub fn broadcast_request(&self, request: T) -> BroadcastResponse<T::ResponseMessage> {
+        self.broadcast_request_filtered(request, |_| true)
+    }
+
+    /// Sends a request to every established connection accepted by `filter` and returns an
+    /// object that gathers the responses of all of them
+    pub fn broadcast_request_filtered(
+        &self,
+        request: T,
+        mut filter: impl FnMut(&ConnectionId) -> bool,
+    ) -> BroadcastResponse<T::ResponseMessage> {
+        let targets: Vec<ConnectionId> = self
+            .server
+            .connection_ids()
+            .filter(|conn_id| filter(conn_id))
+            .collect();
+
+        let mut broadcast = BroadcastResponse {
+            pending: Vec::with_capacity(targets.len()),
+            finished: Vec::new(),
+            clock: self.clock.clone(),
+            deadline: None,
+        };
+
+        for conn_id in targets {
+            match self.send_request(conn_id, request.clone()) {
+                Ok(response) => broadcast.pending.push((conn_id, response)),
+                Err(err) => broadcast
+                    .finished
+                    .push((conn_id, BroadcastResult::Failed(err))),
+            }
+        }
+
+        broadcast
     }
 }
 
@@ -242,6 +445,7 @@ impl<'w, 's, T: RequestMessage, NP: NetworkProvider> Requester<'w, 's, T, NP> {
 #[derive(Debug)]
 pub struct Response<T> {
     rx: Receiver<T>,
+    canceller: Option<RequestCanceller>,
 }
 
 impl<T> Response<T> {
@@ -254,36 +458,294 @@ impl<T> Response<T> {
             Err(self)
         }
     }
+
+    /// Tell the remote that the response is no longer needed and drop the underlying machinery for handling the request.
+    ///
+    /// The remote can check [`Request::is_cancelled`] to stop working on the request early.
+    pub fn cancel(self) -> Result<(), NetworkError> {
+        match self.canceller {
+            Some(canceller) => canceller.cancel(),
+            None => Ok(()),
+        }
+    }
+
+    fn poll(&self) -> Option<Result<T, ()>> {
+        match self.rx.try_recv() {
+            Ok(res) => Some(Ok(res)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Closed) => Some(Err(())),
+        }
+    }
+}
+
+/// The outcome of a broadcast request for a single connection.
+#[derive(Debug)]
+pub enum BroadcastResult<T> {
+    /// The connection answered the request.
+    Response(T),
+    /// The request could not be sent, or the connection closed before answering.
+    Failed(NetworkError),
+    /// The connection did not answer before the deadline set with [`BroadcastResponse::with_timeout`].
+    TimedOut,
+}
+
+/// The eventual responses of a request sent to several connections at once.
+///
+/// Poll it with [`BroadcastResponse::try_recv`] until [`BroadcastResponse::is_complete`] returns true.
+#[derive(Debug)]
+pub struct BroadcastResponse<T> {
+    pending: Vec<(ConnectionId, Response<T>)>,
+    finished: Vec<(ConnectionId, BroadcastResult<T>)>,
+    clock: Clock,
+    /// In the time of `clock`
+    deadline: Option<Duration>,
+}
+
+impl<T> BroadcastResponse<T> {
+    /// Give up on any connection that has not answered within `timeout` from now, measured with the [`Clock`] of the runtime.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.deadline = Some(self.clock.now() + timeout);
+        self
+    }
+
+    /// Returns the results that arrived since the last call.
+    ///
+    /// Every connection the request was sent to is yielded exactly once.
+    pub fn try_recv(&mut self) -> Vec<(ConnectionId, BroadcastResult<T>)> {
+        let timed_out = self
+            .deadline
+            .is_some_and(|deadline| self.clock.now() >= deadline);
+
+        let mut index = 0;
+        while index < self.pending.len() {
+            let (conn_id, response) = &self.pending[index];
+            let result = match response.poll() {
+                Some(Ok(res)) => BroadcastResult::Response(res),
+                Some(Err(())) => BroadcastResult::Failed(NetworkError::ChannelClosed(*conn_id)),
+                None if timed_out => BroadcastResult::TimedOut,
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+            let (conn_id, _) = self.pending.swap_remove(index);
+            self.finished.push((conn_id, result));
+        }
+
+        std::mem::take(&mut self.finished)
+    }
+
+    /// Returns true once every connection has answered, failed or timed out, and all results have been received.
+    pub fn is_complete(&self) -> bool {
+        self.pending.is_empty() && self.finished.is_empty()
+    }
+
+    /// The connections that have not produced a result yet.
+    pub fn pending(&self) -> impl Iterator<Item = &ConnectionId> {
+        self.pending.iter().map(|(conn_id, _)| conn_id)
+    }
+
+    /// Cancel the request on every connection that has not produced a result yet.
+    ///
+    /// Connections that can no longer be reached are skipped.
+    pub fn cancel(self) {
+        for (_, response) in self.pending {
+            let _ = response.cancel();
+        }
+    }
+}
+
+/// Sends the notice that cancels a request on the remote.
+#[derive(Debug)]
+pub(crate) struct RequestCanceller {
+    request_name: &'static str,
+    request_id: u64,
+    tx: Sender<NetworkPacket>,
+}
+
+impl RequestCanceller {
+    pub(crate) fn new<NP: NetworkProvider>(
+        network: &Network<NP>,
+        client_id: ConnectionId,
+        request_name: &'static str,
+        request_id: u64,
+    ) -> Option<Self> {
+        network
+            .established_connections
+            .get(&client_id)
+            .map(|connection| Self {
+                request_name,
+                request_id,
+                tx: connection.send_message.clone(),
+            })
+    }
+
+    pub(crate) fn cancel(self) -> Result<(), NetworkError> {
+        let packet = NetworkPacket {
+            kind: String::from(CancelRequestInternal::NAME),
+            data: bincode::serialize(&CancelRequestInternal {
+                request_name: String::from(self.request_name),
+                request_id: self.request_id,
+            })
+            .map_err(|_| NetworkError::Serialization)?,
+        };
+
+        self.tx
+            .try_send(packet)
+            .map_err(|_| NetworkError::SendError)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CancelRequestInternal {
+    request_name: String,
+    request_id: u64,
+}
+
+impl NetworkMessage for CancelRequestInternal {
+    const NAME: &'static str = "eventwork:CancelRequest";
+}
+
+type CancellationKey = (ConnectionId, String, u64);
+
+/// How many frames a cancellation that arrived before its request is kept around
+const EARLY_CANCELLATION_FRAMES: u8 = 8;
+
+/// Tracks the cancellation flags of the requests received from each connection
+#[derive(Resource)]
+pub(crate) struct RequestCancellations<NP: NetworkProvider> {
+    requests: DashMap<CancellationKey, Weak<AtomicBool>>,
+    early: DashMap<CancellationKey, (Arc<AtomicBool>, u8)>,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for RequestCancellations<NP> {
+    fn default() -> Self {
+        Self {
+            requests: DashMap::new(),
+            early: DashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> RequestCancellations<NP> {
+    /// Returns the flag that will be set once `source` cancels the request
+    pub(crate) fn track(
+        &self,
+        source: ConnectionId,
+        request_name: &'static str,
+        request_id: u64,
+    ) -> Arc<AtomicBool> {
+        let key = (source, String::from(request_name), request_id);
+        let flag = match self.early.remove(&key) {
+            Some((_, (flag, _))) => flag,
+            None => Arc::new(AtomicBool::new(false)),
+        };
+        self.requests.insert(key, Arc::downgrade(&flag));
+        flag
+    }
+}
+
+/// Registers the cancellation notice for the given provider, if it hasn't been already
+pub(crate) fn listen_for_request_cancellations<NP: NetworkProvider>(app: &mut App) {
+    let server = app.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before listening for server messages.");
+
+    if server
+        .recv_message_map
+        .contains_key(CancelRequestInternal::NAME)
+    {
+        return;
+    }
+
+    server
+        .recv_message_map
+        .insert(CancelRequestInternal::NAME, Vec::new());
+    add_data_event::<CancelRequestInternal, NP>(app);
+    app.init_resource::<RequestCancellations<NP>>();
+    let schedules = EventworkSchedules::of::<NP>(app);
+    app.add_systems(
+        schedules.receive,
+        (
+            register_message::<CancelRequestInternal, NP>.in_set(EventworkSet::Receive),
+            handle_request_cancellations::<NP>.in_set(EventworkSet::Requests),
+        ),
+    );
+}
+
+pub(crate) fn handle_request_cancellations<NP: NetworkProvider>(
+    mut cancels: NetworkDataReader<CancelRequestInternal, NP>,
+    cancellations: Res<RequestCancellations<NP>>,
+) {
+    cancellations
+        .requests
+        .retain(|_, flag| flag.strong_count() > 0);
+    cancellations.early.retain(|_, (_, age)| {
+        *age += 1;
+        *age < EARLY_CANCELLATION_FRAMES
+    });
+
+    for cancel in cancels.read() {
+        let key = (
+            *cancel.source(),
+            cancel.request_name.clone(),
+            cancel.request_id,
+        );
+        match cancellations
+            .requests
+            .remove(&key)
+            .and_then(|(_, flag)| flag.upgrade())
+        {
+            Some(flag) => flag.store(true, Ordering::Relaxed),
+            None => {
+                // The request may still be waiting to be read this frame
+                cancellations
+                    .early
+                    .insert(key, (Arc::new(AtomicBool::new(true)), 0));
+            }
+        }
+    }
 }
 
 #[derive(Debug, Resource)]
 /// Technically an internal type, public for use in system pram
-pub struct ResponseMap<T: RequestMessage> {
+pub struct ResponseMap<T: RequestMessage, NP: NetworkProvider> {
     count: AtomicU64,
-    map: DashMap<u64, Sender<T::ResponseMessage>>,
+    map: DashMap<u64, (ConnectionId, Sender<T::ResponseMessage>)>,
+    marker: PhantomData<NP>,
 }
 
-impl<T: RequestMessage> Default for ResponseMap<T> {
+impl<T: RequestMessage, NP: NetworkProvider> Default for ResponseMap<T, NP> {
     fn default() -> Self {
         Self {
             count: Default::default(),
             map: DashMap::new(),
+            marker: PhantomData,
         }
     }
 }
 
-impl<T: RequestMessage> ResponseMap<T> {
-    fn get_responder(&self) -> (u64, Response<T::ResponseMessage>) {
-        let id = self
-            .count
-            .fetch_add(1, core::sync::atomic::Ordering::SeqCst);
+impl<T: RequestMessage, NP: NetworkProvider> ResponseMap<T, NP> {
+    fn get_responder(&self, client_id: ConnectionId) -> (u64, Response<T::ResponseMessage>) {
+        let id = self.count.fetch_add(1, Ordering::SeqCst);
         let (tx, rx) = async_channel::bounded(1);
-        self.map.insert(id, tx);
-        (id, Response { rx })
+        self.map.insert(id, (client_id, tx));
+        (
+            id,
+            Response {
+                rx,
+                canceller: None,
+            },
+        )
     }
 
     fn remove(&self, id: &u64) -> Option<Sender<T::ResponseMessage>> {
-        self.map.remove(id).map(|inner| inner.1)
+        self.map.remove(id).map(|(_, (_, sender))| sender)
+    }
+
+    /// Drops every responder waiting on `client_id`, closing their [`Response`]s.
+    fn remove_connection(&self, client_id: ConnectionId) {
+        self.map.retain(|_, (conn_id, _)| *conn_id != client_id);
     }
 }
 
@@ -323,6 +785,7 @@ pub struct Request<T: RequestMessage> {
     source: ConnectionId,
     request_id: u64,
     response_tx: Sender<NetworkPacket>,
+    cancelled: Arc<AtomicBool>,
 }
 
 impl<T: RequestMessage> Request<T> {
@@ -338,8 +801,20 @@ impl<T: RequestMessage> Request<T> {
         &self.source
     }
 
+    /// Returns true if the client has cancelled the request, and no longer needs a response.
+    #[inline(always)]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
     /// Consume the request and automatically send the response back to the client.
+    ///
+    /// If the request was cancelled, nothing is sent.
     pub fn respond(self, response: T::ResponseMessage) -> Result<(), NetworkError> {
+        if self.is_cancelled() {
+            return Ok(());
+        }
+
         let packet = NetworkPacket {
             kind: String::from(T::ResponseMessage::NAME),
             data: bincode::serialize(&ResponseInternal {
@@ -359,6 +834,45 @@ impl<T: RequestMessage> Request<T> {
 pub trait AppNetworkRequestMessage {
     /// Register a request message type to listen for in the app
     fn listen_for_request_message<T: RequestMessage, NP: NetworkProvider>(&mut self) -> &mut Self;
+
+    /// Register a system that answers every request of the given type.
+    ///
+    /// The system receives the source and the request as `In<(ConnectionId, T)>`, can use any other
+    /// system params, and returns either the response or a `Result` whose error converts into the response.
+    /// Eventwork sends the returned response itself, so every request gets answered.
+    ///
+    /// This will also register the request message if that hasn't been done with
+    /// [`AppNetworkRequestMessage::listen_for_request_message`] yet.
+    ///
+    /// ## Panics
+    /// Only one handler can be registered per request type.
+    fn add_request_handler<T: RequestMessage, NP: NetworkProvider, M>(
+        &mut self,
+        handler: impl IntoRequestHandler<T, M>,
+    ) -> &mut Self;
+
+    /// Register an async function that answers every request of the given type in the background.
+    ///
+    /// For every request, the `C` resource is cloned and passed to `handler` together with the source and the request.
+    /// The returned future is spawned on the [`EventworkRuntime`], and whatever it resolves to is sent
+    /// back on the connection the request came from, regardless of how many frames that takes.
+    ///
+    /// This will also register the request message if that hasn't been done with
+    /// [`AppNetworkRequestMessage::listen_for_request_message`] yet.
+    ///
+    /// ## Panics
+    /// Only one handler can be registered per request type.
+    fn add_async_request_handler<T, NP, RT, C, Fut>(
+        &mut self,
+        handler: impl Fn(ConnectionId, T, C) -> Fut + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        T: RequestMessage,
+        NP: NetworkProvider,
+        RT: Runtime,
+        C: Resource + Clone,
+        Fut: Future + Send + 'static,
+        Fut::Output: IntoResponse<T::ResponseMessage>;
 }
 
 impl AppNetworkRequestMessage for App {
@@ -380,22 +894,246 @@ impl AppNetworkRequestMessage for App {
         server
             .recv_message_map
             .insert(RequestInternal::<T>::NAME, Vec::new());
-        self.add_event::<NetworkData<RequestInternal<T>>>();
+        add_data_event::<RequestInternal<T>, NP>(self);
         self.add_event::<Request<T>>();
+        listen_for_request_cancellations::<NP>(self);
+        let schedules = EventworkSchedules::of::<NP>(self);
         self.add_systems(
-            PreUpdate,
+            schedules.receive,
             (
-                create_request_handlers::<T, NP>,
-                register_message::<RequestInternal<T>, NP>,
+                register_message::<RequestInternal<T>, NP>.in_set(EventworkSet::Receive),
+                create_request_handlers::<T, NP>
+                    .in_set(EventworkSet::Requests)
+                    .before(handle_request_cancellations::<NP>),
             ),
         )
     }
+
+    fn add_request_handler<T: RequestMessage, NP: NetworkProvider, M>(
+        &mut self,
+        handler: impl IntoRequestHandler<T, M>,
+    ) -> &mut Self {
+        prepare_request_handler::<T, NP>(self);
+
+        debug!(
+            "Registered a new request handler: {}",
+            RequestInternal::<T>::NAME
+        );
+
+        self.insert_resource(RequestHandler::<T> {
+            system: handler.into_request_handler(),
+            initialized: false,
+            reader: Default::default(),
+        });
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.add_systems(
+            schedules.receive,
+            run_request_handler::<T, NP>
+                .in_set(EventworkSet::Requests)
+                .after(create_request_handlers::<T, NP>),
+        )
+    }
+
+    fn add_async_request_handler<T, NP, RT, C, Fut>(
+        &mut self,
+        handler: impl Fn(ConnectionId, T, C) -> Fut + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        T: RequestMessage,
+        NP: NetworkProvider,
+        RT: Runtime,
+        C: Resource + Clone,
+        Fut: Future + Send + 'static,
+        Fut::Output: IntoResponse<T::ResponseMessage>,
+    {
+        prepare_request_handler::<T, NP>(self);
+
+        debug!(
+            "Registered a new async request handler: {}",
+            RequestInternal::<T>::NAME
+        );
+
+        self.insert_resource(AsyncRequestHandler::<T, C> {
+            handler: Box::new(move |source, request, context| {
+                let response = handler(source, request, context);
+                Box::pin(async move { response.await.into_response() })
+            }),
+            tasks: HashMap::new(),
+            task_count: 0,
+            finished_tasks: AsyncChannel::new(),
+        });
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.add_systems(
+            schedules.receive,
+            spawn_async_request_handlers::<T, RT, C>
+                .in_set(EventworkSet::Requests)
+                .after(create_request_handlers::<T, NP>),
+        )
+    }
+}
+
+/// Registers the request message if needed and makes sure no other handler exists for it
+fn prepare_request_handler<T: RequestMessage, NP: NetworkProvider>(app: &mut App) {
+    let registered = app
+        .world()
+        .get_resource::<Network<NP>>()
+        .is_some_and(|server| {
+            server
+                .recv_message_map
+                .contains_key(RequestInternal::<T>::NAME)
+        });
+    if !registered {
+        app.listen_for_request_message::<T, NP>();
+    }
+
+    assert!(
+        !app.world().contains_resource::<RequestHandlerMarker<T>>(),
+        "Duplicate request handler for RequestMessage: {}",
+        RequestInternal::<T>::NAME
+    );
+    app.insert_resource(RequestHandlerMarker::<T>(PhantomData));
+}
+
+/// A system that can be registered with [`AppNetworkRequestMessage::add_request_handler`].
+///
+/// Implemented for any system that takes `In<(ConnectionId, T)>` and returns an [`IntoResponse`].
+pub trait IntoRequestHandler<T: RequestMessage, M> {
+    /// Turn the value into a boxed system returning the response
+    fn into_request_handler(
+        self,
+    ) -> Box<dyn System<In = (ConnectionId, T), Out = T::ResponseMessage>>;
+}
+
+impl<T, R, M, S> IntoRequestHandler<T, (R, M)> for S
+where
+    T: RequestMessage,
+    R: IntoResponse<T::ResponseMessage> + 'static,
+    S: IntoSystem<(ConnectionId, T), R, M>,
+{
+    fn into_request_handler(
+        self,
+    ) -> Box<dyn System<In = (ConnectionId, T), Out = T::ResponseMessage>> {
+        Box::new(IntoSystem::into_system(
+            self.map(IntoResponse::into_response),
+        ))
+    }
+}
+
+/// The return value of a request handler registered with [`AppNetworkRequestMessage::add_request_handler`].
+///
+/// Implemented for the response itself, and for any `Result` whose error converts into the response.
+pub trait IntoResponse<R> {
+    /// Turn the value into the response sent back to the requester
+    fn into_response(self) -> R;
+}
+
+impl<R: NetworkMessage> IntoResponse<R> for R {
+    fn into_response(self) -> R {
+        self
+    }
+}
+
+impl<R: NetworkMessage, E: Into<R>> IntoResponse<R> for Result<R, E> {
+    fn into_response(self) -> R {
+        self.unwrap_or_else(Into::into)
+    }
+}
+
+/// Marks that a handler, sync or async, has been registered for `T`
+#[derive(Resource)]
+struct RequestHandlerMarker<T>(PhantomData<T>);
+
+type BoxedResponseFuture<R> = Pin<Box<dyn Future<Output = R> + Send>>;
+
+#[derive(Resource)]
+struct AsyncRequestHandler<T: RequestMessage, C> {
+    handler:
+        Box<dyn Fn(ConnectionId, T, C) -> BoxedResponseFuture<T::ResponseMessage> + Send + Sync>,
+    tasks: HashMap<u64, (Box<dyn JoinHandle>, Arc<AtomicBool>)>,
+    task_count: u64,
+    finished_tasks: AsyncChannel<u64>,
+}
+
+fn spawn_async_request_handlers<T: RequestMessage, RT: Runtime, C: Resource + Clone>(
+    mut requests: EventReader<Request<T>>,
+    mut handler: ResMut<AsyncRequestHandler<T, C>>,
+    context: Res<C>,
+    runtime: Res<EventworkRuntime<RT>>,
+) {
+    while let Ok(task_id) = handler.finished_tasks.receiver.try_recv() {
+        handler.tasks.remove(&task_id);
+    }
+
+    // Stop working on anything the requester no longer needs
+    handler.tasks.retain(|_, (task, cancelled)| {
+        if cancelled.load(Ordering::Relaxed) {
+            task.abort();
+            false
+        } else {
+            true
+        }
+    });
+
+    for request in requests.read() {
+        let task_id = handler.task_count;
+        handler.task_count += 1;
+
+        let response = (handler.handler)(request.source, request.request.clone(), context.clone());
+        let request = request.clone();
+        let cancelled = request.cancelled.clone();
+        let finished_tasks = handler.finished_tasks.sender.clone();
+
+        let task = run_async(
+            async move {
+                let source = request.source;
+                if let Err(err) = request.respond(response.await) {
+                    error!("Could not send async response to {}: {}", source, err);
+                }
+                let _ = finished_tasks.send(task_id).await;
+            },
+            &runtime.0,
+        );
+        handler.tasks.insert(task_id, (Box::new(task), cancelled));
+    }
+}
+
+#[derive(Resource)]
+struct RequestHandler<T: RequestMessage> {
+    system: Box<dyn System<In = (ConnectionId, T), Out = T::ResponseMessage>>,
+    initialized: bool,
+    reader: ManualEventReader<Request<T>>,
+}
+
+fn run_request_handler<T: RequestMessage, NP: NetworkProvider>(world: &mut World) {
+    world.resource_scope(|world, mut handler: Mut<RequestHandler<T>>| {
+        let handler = handler.as_mut();
+        if !handler.initialized {
+            handler.system.initialize(world);
+            handler.initialized = true;
+        }
+
+        let requests: Vec<Request<T>> = handler
+            .reader
+            .read(world.resource::<Events<Request<T>>>())
+            .cloned()
+            .collect();
+
+        for request in requests {
+            let response = handler
+                .system
+                .run((request.source, request.request.clone()), world);
+            if let Err(err) = request.respond(response) {
+                send_network_event::<NP>(world, NetworkEvent::Error(err));
+            }
+        }
+    });
 }
 
 fn create_request_handlers<T: RequestMessage, NP: NetworkProvider>(
-    mut requests: EventReader<NetworkData<RequestInternal<T>>>,
+    mut requests: NetworkDataReader<RequestInternal<T>, NP>,
     mut requests_wrapped: EventWriter<Request<T>>,
     network: Res<Network<NP>>,
+    cancellations: Res<RequestCancellations<NP>>,
 ) {
     for request in requests.read() {
         if let Some(connection) = &network.established_connections.get(request.source()) {
@@ -404,6 +1142,7 @@ fn create_request_handlers<T: RequestMessage, NP: NetworkProvider>(
                 request_id: request.id,
                 response_tx: connection.send_message.clone(),
                 source: request.source,
+                cancelled: cancellations.track(request.source, T::REQUEST_NAME, request.id),
             });
         }
     }
@@ -427,7 +1166,7 @@ pub trait AppNetworkResponseMessage {
 
 impl AppNetworkResponseMessage for App {
     fn listen_for_response_message<T: RequestMessage, NP: NetworkProvider>(&mut self) -> &mut Self {
-        self.insert_resource(ResponseMap::<T>::default());
+        self.insert_resource(ResponseMap::<T, NP>::default());
         let client = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before listening for server messages.");
 
         debug!(
@@ -445,26 +1184,127 @@ impl AppNetworkResponseMessage for App {
         client
             .recv_message_map
             .insert(ResponseInternal::<T::ResponseMessage>::NAME, Vec::new());
-        self.add_event::<NetworkData<ResponseInternal<T::ResponseMessage>>>();
+        let schedules = client.schedules;
+        add_data_event::<ResponseInternal<T::ResponseMessage>, NP>(self);
         self.add_systems(
-            PreUpdate,
+            schedules.receive,
             (
-                register_message::<ResponseInternal<T::ResponseMessage>, NP>,
-                create_client_response_handlers::<T>,
+                register_message::<ResponseInternal<T::ResponseMessage>, NP>
+                    .in_set(EventworkSet::Receive),
+                create_client_response_handlers::<T, NP>.in_set(EventworkSet::Requests),
             ),
         )
     }
 }
 
-fn create_client_response_handlers<T: RequestMessage>(
-    mut responses: EventReader<NetworkData<ResponseInternal<T::ResponseMessage>>>,
-    response_map: ResMut<ResponseMap<T>>,
+fn create_client_response_handlers<T: RequestMessage, NP: NetworkProvider>(
+    mut responses: NetworkDataReader<ResponseInternal<T::ResponseMessage>, NP>,
+    mut network_events: NetworkEventReader<NP>,
+    response_map: ResMut<ResponseMap<T, NP>>,
 ) {
+    // Forget requests whose response object was dropped or cancelled
+    response_map
+        .map
+        .retain(|_, (_, sender)| !sender.is_closed());
+
     for response in responses.read() {
         if let Some(sender) = response_map.remove(&response.response_id) {
-            sender
-                .try_send(response.response.clone())
-                .expect("Internal channel closed!");
+            let _ = sender.try_send(response.response.clone());
         }
     }
+
+    // After the responses, which may have arrived right before the connection was lost
+    for event in network_events.read() {
+        if let NetworkEvent::Disconnected(conn_id) = event {
+            response_map.remove_connection(*conn_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::In;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{tcp::TcpProvider, EventworkPlugin, SimulatedRuntime};
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    struct Ping;
+
+    impl RequestMessage for Ping {
+        type ResponseMessage = Pong;
+        const REQUEST_NAME: &'static str = "test:Ping";
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    struct Pong;
+
+    impl NetworkMessage for Pong {
+        const NAME: &'static str = "test:Pong";
+    }
+
+    #[derive(Resource, Clone)]
+    struct Context;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+        app
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate request handler")]
+    fn sync_and_async_handlers_conflict() {
+        app()
+            .add_request_handler::<Ping, TcpProvider, _>(|In((_, _)): In<(ConnectionId, Ping)>| {
+                Pong
+            })
+            .add_async_request_handler::<Ping, TcpProvider, SimulatedRuntime, Context, _>(
+                |_, _, _| async { Pong },
+            );
+    }
+
+    #[test]
+    #[should_panic(expected = "Duplicate request handler")]
+    fn async_and_sync_handlers_conflict() {
+        app()
+            .add_async_request_handler::<Ping, TcpProvider, SimulatedRuntime, Context, _>(
+                |_, _, _| async { Pong },
+            )
+            .add_request_handler::<Ping, TcpProvider, _>(|In((_, _)): In<(ConnectionId, Ping)>| {
+                Pong
+            });
+    }
+
+    #[test]
+    fn broadcast_times_out_with_the_runtime_clock() {
+        let runtime = SimulatedRuntime::new();
+        let (_tx, rx) = async_channel::unbounded::<()>();
+        let conn_id = ConnectionId { id: 0 };
+        let mut broadcast = BroadcastResponse {
+            pending: vec![(
+                conn_id,
+                Response {
+                    rx,
+                    canceller: None,
+                },
+            )],
+            finished: Vec::new(),
+            clock: runtime.clock(),
+            deadline: None,
+        }
+        .with_timeout(Duration::from_secs(5));
+
+        runtime.advance(Duration::from_secs(4));
+        assert!(broadcast.try_recv().is_empty());
+
+        runtime.advance(Duration::from_secs(1));
+        let results = broadcast.try_recv();
+        assert!(matches!(
+            results.as_slice(),
+            [(id, BroadcastResult::TimedOut)] if *id == conn_id
+        ));
+        assert!(broadcast.is_complete());
+    }
 }
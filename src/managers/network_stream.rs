@@ -0,0 +1,702 @@
+//! # Streaming Request/Response Network Messages
+//!
+//! Some requests produce their results over time, like a server browser listing or a paginated leaderboard.
+//! A [`StreamingRequestMessage`](self::network_stream::StreamingRequestMessage) works like a [`RequestMessage`](self::network_request::RequestMessage),
+//! except that the responder can send any number of items followed by an end marker, instead of a single response.
+//!
+//! ## Overview
+//!
+//! **Client**
+//!
+//! - Client sends a request using the [`StreamingRequester`](self::network_stream::StreamingRequester) system param.
+//! - On successful sends, a [`ResponseStream`](self::network_stream::ResponseStream) is returned that is tied to the id of the request.
+//! - Client polls the stream every frame, reading items as they arrive, until [`ResponseStream::is_finished`](self::network_stream::ResponseStream::is_finished) returns true.
+//!
+//! **Server**
+//!
+//! - Server listens for requests of the given type, which arrive as [`StreamingRequest`](self::network_stream::StreamingRequest) events.
+//! - Server sends items with [`StreamingRequest::send`](self::network_stream::StreamingRequest::send), possibly over many frames.
+//! - Server ends the stream with [`StreamingRequest::finish`](self::network_stream::StreamingRequest::finish).
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkMessage,
+//!     ConnectionId,
+//!     tcp::TcpProvider,
+//!     managers::network_stream::{
+//!     AppNetworkStreamingRequestMessage,
+//!     AppNetworkStreamingResponseMessage,
+//!     ResponseStream,
+//!     StreamingRequest,
+//!     StreamingRequester,
+//!     StreamingRequestMessage},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Debug, Serialize, Deserialize, Clone)]
+//! struct ListServers;
+//!
+//! impl StreamingRequestMessage for ListServers {
+//!     type ResponseItem = ServerListing;
+//!     const REQUEST_NAME: &'static str = "example:ListServers";
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, Clone)]
+//! struct ServerListing {
+//!     name: String,
+//! }
+//!
+//! impl NetworkMessage for ServerListing {
+//!     const NAME: &'static str = "example:ServerListing";
+//! }
+//!
+//! fn client(app: &mut App) {
+//!     app.listen_for_streaming_response_message::<ListServers, TcpProvider>();
+//! }
+//!
+//! fn server(app: &mut App) {
+//!     app.listen_for_streaming_request_message::<ListServers, TcpProvider>();
+//! }
+//!
+//! #[derive(Resource)]
+//! struct Listings(ResponseStream<ServerListing>);
+//!
+//! fn request_listings(net: StreamingRequester<ListServers, TcpProvider>, mut commands: Commands) {
+//!     if let Ok(stream) = net.send_request(ConnectionId { id: 0 }, ListServers) {
+//!         commands.insert_resource(Listings(stream));
+//!     }
+//! }
+//!
+//! fn read_listings(mut listings: ResMut<Listings>, mut commands: Commands) {
+//!     while let Some(listing) = listings.0.try_next() {
+//!         println!("Found server: {}", listing.name);
+//!     }
+//!     if listings.0.is_finished() {
+//!         commands.remove_resource::<Listings>();
+//!     }
+//! }
+//!
+//! fn answer_listings(mut requests: EventReader<StreamingRequest<ListServers>>) {
+//!     for request in requests.read() {
+//!         for name in ["Alpha", "Beta"] {
+//!             let _ = request.send(ServerListing { name: name.to_string() });
+//!         }
+//!         let _ = request.clone().finish();
+//!     }
+//! }
+//! ```
+
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_channel::{Receiver, Sender};
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{debug, App, Event, EventWriter, IntoSystemConfigs, Res, ResMut, Resource},
+};
+use dashmap::DashMap;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    error::NetworkError,
+    labelled::{add_data_event, NetworkDataReader, NetworkEventReader},
+    ConnectionId, EventworkSchedules, EventworkSet, NetworkEvent, NetworkMessage, NetworkPacket,
+};
+
+use super::{
+    network::register_message,
+    network_request::{
+        handle_request_cancellations, listen_for_request_cancellations, RequestCancellations,
+        RequestCanceller,
+    },
+    Network, NetworkProvider,
+};
+
+#[derive(SystemParam, Debug)]
+/// A wrapper around [`Network`] that allows for the sending of [`StreamingRequestMessage`]'s.
+pub struct StreamingRequester<'w, 's, T: StreamingRequestMessage, NP: NetworkProvider> {
+    server: Res<'w, Network<NP>>,
+    stream_map: Res<'w, ResponseStreamMap<T, NP>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s usize>,
+}
+
+impl<'w, 's, T: StreamingRequestMessage, NP: NetworkProvider> StreamingRequester<'w, 's, T, NP> {
+    /// Sends a request and returns a stream that will eventually yield the response items
+    pub fn send_request(
+        &self,
+        client_id: ConnectionId,
+        request: T,
+    ) -> Result<ResponseStream<T::ResponseItem>, NetworkError> {
+        let (id, stream) = self.stream_map.get_stream(client_id);
+        if let Err(err) = self
+            .server
+            .send_message(client_id, StreamingRequestInternal { id, request })
+        {
+            self.stream_map.remove(&id);
+            return Err(err);
+        }
+        Ok(ResponseStream {
+            canceller: RequestCanceller::new(&self.server, client_id, T::REQUEST_NAME, id),
+            ..stream
+        })
+    }
+}
+
+/// The items of a remote streaming request, as they arrive.
+#[derive(Debug)]
+pub struct ResponseStream<T> {
+    request_id: u64,
+    rx: Receiver<T>,
+    canceller: Option<RequestCanceller>,
+}
+
+impl<T> ResponseStream<T> {
+    /// The id of the request this stream belongs to
+    #[inline(always)]
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// Try to receive the next item of the stream, returns [`None`] if no item has arrived yet.
+    pub fn try_next(&mut self) -> Option<T> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Returns true once the responder has ended the stream, or the connection was lost,
+    /// and every item has been read.
+    pub fn is_finished(&self) -> bool {
+        self.rx.is_closed() && self.rx.is_empty()
+    }
+
+    /// Tell the remote that no more items are needed and drop the underlying machinery for handling the request.
+    ///
+    /// The remote can check [`StreamingRequest::is_cancelled`] to stop producing items early.
+    pub fn cancel(self) -> Result<(), NetworkError> {
+        match self.canceller {
+            Some(canceller) => canceller.cancel(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+/// Technically an internal type, public for use in system pram
+pub struct ResponseStreamMap<T: StreamingRequestMessage, NP: NetworkProvider> {
+    count: AtomicU64,
+    map: DashMap<u64, (ConnectionId, Sender<T::ResponseItem>)>,
+    marker: PhantomData<NP>,
+}
+
+impl<T: StreamingRequestMessage, NP: NetworkProvider> Default for ResponseStreamMap<T, NP> {
+    fn default() -> Self {
+        Self {
+            count: Default::default(),
+            map: DashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T: StreamingRequestMessage, NP: NetworkProvider> ResponseStreamMap<T, NP> {
+    fn get_stream(&self, client_id: ConnectionId) -> (u64, ResponseStream<T::ResponseItem>) {
+        let id = self.count.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = async_channel::unbounded();
+        self.map.insert(id, (client_id, tx));
+        (
+            id,
+            ResponseStream {
+                request_id: id,
+                rx,
+                canceller: None,
+            },
+        )
+    }
+
+    fn remove(&self, id: &u64) -> Option<Sender<T::ResponseItem>> {
+        self.map.remove(id).map(|(_, (_, sender))| sender)
+    }
+
+    /// Drops every stream waiting on `client_id`, ending their [`ResponseStream`]s.
+    fn remove_connection(&self, client_id: ConnectionId) {
+        self.map.retain(|_, (conn_id, _)| *conn_id != client_id);
+    }
+}
+
+/// Marks a type as a streaming request type.
+pub trait StreamingRequestMessage:
+    Clone + Serialize + DeserializeOwned + Send + Sync + Debug + 'static
+{
+    /// The type of each item sent back for the request.
+    type ResponseItem: NetworkMessage
+        + Clone
+        + Serialize
+        + DeserializeOwned
+        + Send
+        + Sync
+        + Debug
+        + 'static;
+
+    /// The label used for the request type, same rules as [`NetworkMessage`] in terms of naming.
+    const REQUEST_NAME: &'static str;
+}
+
+#[derive(Serialize, Deserialize)]
+struct StreamingRequestInternal<T> {
+    id: u64,
+    request: T,
+}
+
+impl<T: StreamingRequestMessage> NetworkMessage for StreamingRequestInternal<T> {
+    const NAME: &'static str = T::REQUEST_NAME;
+}
+
+/// A wrapper around a streaming request that allows sending any number of items, followed by an end marker,
+///  that will automatically be written to eventwork for network transmission.
+#[derive(Debug, Event, Clone)]
+pub struct StreamingRequest<T: StreamingRequestMessage> {
+    request: T,
+    source: ConnectionId,
+    request_id: u64,
+    response_tx: Sender<NetworkPacket>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<T: StreamingRequestMessage> StreamingRequest<T> {
+    /// Read the underlying request
+    #[inline(always)]
+    pub fn get_request(&self) -> &T {
+        &self.request
+    }
+
+    /// Read the source of the underlying request
+    #[inline(always)]
+    pub fn source(&self) -> &ConnectionId {
+        &self.source
+    }
+
+    /// Returns true if the client has cancelled the request, and no longer needs any items.
+    #[inline(always)]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Send a single item of the response back to the client.
+    ///
+    /// If the request was cancelled, nothing is sent.
+    pub fn send(&self, item: T::ResponseItem) -> Result<(), NetworkError> {
+        self.send_internal(Some(item))
+    }
+
+    /// Consume the request and tell the client that no more items will follow.
+    ///
+    /// If the request was cancelled, nothing is sent.
+    pub fn finish(self) -> Result<(), NetworkError> {
+        self.send_internal(None)
+    }
+
+    fn send_internal(&self, item: Option<T::ResponseItem>) -> Result<(), NetworkError> {
+        if self.is_cancelled() {
+            return Ok(());
+        }
+
+        let packet = NetworkPacket {
+            kind: String::from(T::ResponseItem::NAME),
+            data: bincode::serialize(&StreamItemInternal {
+                response_id: self.request_id,
+                item,
+            })
+            .map_err(|_| NetworkError::Serialization)?,
+        };
+
+        self.response_tx
+            .try_send(packet)
+            .map_err(|_| NetworkError::SendError)
+    }
+}
+
+/// A utility trait on [`App`] to easily register [`StreamingRequestMessage`]s for the app to recieve
+pub trait AppNetworkStreamingRequestMessage {
+    /// Register a streaming request message type to listen for in the app
+    fn listen_for_streaming_request_message<T: StreamingRequestMessage, NP: NetworkProvider>(
+        &mut self,
+    ) -> &mut Self;
+}
+
+impl AppNetworkStreamingRequestMessage for App {
+    fn listen_for_streaming_request_message<T: StreamingRequestMessage, NP: NetworkProvider>(
+        &mut self,
+    ) -> &mut Self {
+        let server = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before listening for server messages.");
+
+        debug!(
+            "Registered a new StreamingRequestMessage: {}",
+            StreamingRequestInternal::<T>::NAME
+        );
+
+        assert!(
+            !server
+                .recv_message_map
+                .contains_key(StreamingRequestInternal::<T>::NAME),
+            "Duplicate registration of StreamingRequestMessage: {}",
+            StreamingRequestInternal::<T>::NAME
+        );
+        server
+            .recv_message_map
+            .insert(StreamingRequestInternal::<T>::NAME, Vec::new());
+        add_data_event::<StreamingRequestInternal<T>, NP>(self);
+        self.add_event::<StreamingRequest<T>>();
+        listen_for_request_cancellations::<NP>(self);
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.add_systems(
+            schedules.receive,
+            (
+                register_message::<StreamingRequestInternal<T>, NP>.in_set(EventworkSet::Receive),
+                create_streaming_request_handlers::<T, NP>
+                    .in_set(EventworkSet::Requests)
+                    .before(handle_request_cancellations::<NP>),
+            ),
+        )
+    }
+}
+
+fn create_streaming_request_handlers<T: StreamingRequestMessage, NP: NetworkProvider>(
+    mut requests: NetworkDataReader<StreamingRequestInternal<T>, NP>,
+    mut requests_wrapped: EventWriter<StreamingRequest<T>>,
+    network: Res<Network<NP>>,
+    cancellations: Res<RequestCancellations<NP>>,
+) {
+    for request in requests.read() {
+        if let Some(connection) = &network.established_connections.get(request.source()) {
+            requests_wrapped.send(StreamingRequest {
+                request: request.request.clone(),
+                request_id: request.id,
+                response_tx: connection.send_message.clone(),
+                source: request.source,
+                cancelled: cancellations.track(request.source, T::REQUEST_NAME, request.id),
+            });
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StreamItemInternal<T> {
+    response_id: u64,
+    /// [`None`] marks the end of the stream.
+    item: Option<T>,
+}
+
+impl<T: NetworkMessage> NetworkMessage for StreamItemInternal<T> {
+    const NAME: &'static str = T::NAME;
+}
+
+/// A utility trait on [`App`] to easily register [`StreamingRequestMessage::ResponseItem`]s for clients to recieve
+pub trait AppNetworkStreamingResponseMessage {
+    /// Register the response items from the streaming request message type to listen for in the app
+    fn listen_for_streaming_response_message<T: StreamingRequestMessage, NP: NetworkProvider>(
+        &mut self,
+    ) -> &mut Self;
+}
+
+impl AppNetworkStreamingResponseMessage for App {
+    fn listen_for_streaming_response_message<T: StreamingRequestMessage, NP: NetworkProvider>(
+        &mut self,
+    ) -> &mut Self {
+        self.insert_resource(ResponseStreamMap::<T, NP>::default());
+        let client = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before listening for server messages.");
+
+        debug!(
+            "Registered a new streaming ResponseMessage: {}",
+            StreamItemInternal::<T::ResponseItem>::NAME
+        );
+
+        assert!(
+            !client
+                .recv_message_map
+                .contains_key(StreamItemInternal::<T::ResponseItem>::NAME),
+            "Duplicate registration of streaming ResponseMessage: {}",
+            StreamItemInternal::<T::ResponseItem>::NAME
+        );
+        client
+            .recv_message_map
+            .insert(StreamItemInternal::<T::ResponseItem>::NAME, Vec::new());
+        let schedules = client.schedules;
+        add_data_event::<StreamItemInternal<T::ResponseItem>, NP>(self);
+        self.add_systems(
+            schedules.receive,
+            (
+                register_message::<StreamItemInternal<T::ResponseItem>, NP>
+                    .in_set(EventworkSet::Receive),
+                create_client_stream_handlers::<T, NP>.in_set(EventworkSet::Requests),
+            ),
+        )
+    }
+}
+
+fn create_client_stream_handlers<T: StreamingRequestMessage, NP: NetworkProvider>(
+    mut items: NetworkDataReader<StreamItemInternal<T::ResponseItem>, NP>,
+    mut network_events: NetworkEventReader<NP>,
+    stream_map: ResMut<ResponseStreamMap<T, NP>>,
+) {
+    // Forget streams that were dropped or cancelled by the requester
+    stream_map.map.retain(|_, (_, sender)| !sender.is_closed());
+
+    for item in items.read() {
+        match &item.item {
+            Some(inner) => {
+                let closed = match stream_map.map.get(&item.response_id) {
+                    Some(entry) => entry.1.try_send(inner.clone()).is_err(),
+                    None => false,
+                };
+                // The stream was dropped by the requester, stop tracking it
+                if closed {
+                    stream_map.remove(&item.response_id);
+                }
+            }
+            None => {
+                stream_map.remove(&item.response_id);
+            }
+        }
+    }
+
+    // After the items, which may have arrived right before the connection was lost
+    for event in network_events.read() {
+        if let NetworkEvent::Disconnected(conn_id) = event {
+            stream_map.remove_connection(*conn_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, time::Duration};
+
+    use bevy::{
+        ecs::{
+            event::{Events, ManualEventReader},
+            system::RunSystemOnce,
+        },
+        prelude::Mut,
+    };
+
+    use super::*;
+    use crate::{
+        runtime::EventworkRuntime,
+        tcp::{NetworkSettings, TcpProvider},
+        EventworkPlugin, SimulatedRuntime,
+    };
+
+    #[derive(Serialize, Deserialize, Clone, Debug)]
+    struct ListServers;
+
+    impl StreamingRequestMessage for ListServers {
+        type ResponseItem = ServerListing;
+        const REQUEST_NAME: &'static str = "test:ListServers";
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct ServerListing(u32);
+
+    impl NetworkMessage for ServerListing {
+        const NAME: &'static str = "test:ServerListing";
+    }
+
+    /// A server and a client connected to it over loopback
+    struct Peers {
+        runtime: SimulatedRuntime,
+        server: App,
+        client: App,
+        requests: ManualEventReader<StreamingRequest<ListServers>>,
+    }
+
+    impl Peers {
+        fn connect() -> Self {
+            let runtime = SimulatedRuntime::new();
+            let mut server = App::new();
+            server.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+            server.insert_resource(NetworkSettings::default());
+            server.insert_resource(EventworkRuntime(runtime.clone()));
+            server.listen_for_streaming_request_message::<ListServers, TcpProvider>();
+
+            let mut client = App::new();
+            client.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+            client.insert_resource(NetworkSettings::default());
+            client.insert_resource(EventworkRuntime(runtime.clone()));
+            client.listen_for_streaming_response_message::<ListServers, TcpProvider>();
+
+            // A free port, for the server to listen on
+            let addr = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("No free port");
+            server
+                .world_mut()
+                .resource_scope(|world, mut net: Mut<Network<TcpProvider>>| {
+                    net.listen(addr, &runtime, world.resource::<NetworkSettings>())
+                })
+                .expect("Failed to listen");
+            // Binds the listener
+            runtime.run_until_stalled();
+            client.world().resource::<Network<TcpProvider>>().connect(
+                addr,
+                &runtime,
+                &NetworkSettings::default(),
+            );
+
+            let mut peers = Self {
+                runtime,
+                server,
+                client,
+                requests: ManualEventReader::default(),
+            };
+            peers.update_until(|peers| {
+                peers.net(true).connection_ids().count() == 1
+                    && peers.net(false).connection_ids().count() == 1
+            });
+            peers
+        }
+
+        fn net(&self, server: bool) -> &Network<TcpProvider> {
+            let app = if server { &self.server } else { &self.client };
+            app.world().resource::<Network<TcpProvider>>()
+        }
+
+        fn update(&mut self) {
+            self.runtime.run_until_stalled();
+            self.server.update();
+            self.client.update();
+        }
+
+        fn update_until(&mut self, mut done: impl FnMut(&mut Self) -> bool) {
+            for _ in 0..200 {
+                self.update();
+                if done(self) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("Timed out");
+        }
+
+        fn request(&mut self) -> ResponseStream<ServerListing> {
+            let server = self
+                .net(false)
+                .connection_ids()
+                .next()
+                .expect("Not connected");
+            self.client
+                .world_mut()
+                .run_system_once(move |net: StreamingRequester<ListServers, TcpProvider>| {
+                    net.send_request(server, ListServers)
+                })
+                .expect("Failed to send the request")
+        }
+
+        /// Waits until the server received the request
+        fn respond(&mut self) -> StreamingRequest<ListServers> {
+            let mut request = None;
+            self.update_until(|peers| {
+                let events = peers
+                    .server
+                    .world()
+                    .resource::<Events<StreamingRequest<ListServers>>>();
+                request = peers.requests.read(events).next().cloned();
+                request.is_some()
+            });
+            request.expect("No request arrived")
+        }
+    }
+
+    #[test]
+    fn items_arrive_followed_by_the_end() {
+        let mut peers = Peers::connect();
+        let mut stream = peers.request();
+        let request = peers.respond();
+        for listing in 0..3 {
+            request
+                .send(ServerListing(listing))
+                .expect("Failed to send an item");
+        }
+        request.finish().expect("Failed to finish");
+
+        let mut items = Vec::new();
+        peers.update_until(|_| {
+            while let Some(item) = stream.try_next() {
+                items.push(item);
+            }
+            stream.is_finished()
+        });
+        assert_eq!(
+            items,
+            vec![ServerListing(0), ServerListing(1), ServerListing(2)]
+        );
+    }
+
+    #[test]
+    fn cancelling_reaches_the_responder() {
+        let mut peers = Peers::connect();
+        let stream = peers.request();
+        let request = peers.respond();
+        assert!(!request.is_cancelled());
+
+        stream.cancel().expect("Failed to cancel");
+        peers.update_until(|_| request.is_cancelled());
+        // Items after the cancellation aren't sent anymore
+        request
+            .send(ServerListing(0))
+            .expect("Failed to skip the item");
+        request.finish().expect("Failed to skip the end");
+    }
+
+    #[test]
+    fn items_arriving_with_the_disconnect_are_delivered() {
+        let mut peers = Peers::connect();
+        let mut stream = peers.request();
+        let request = peers.respond();
+        request
+            .send(ServerListing(0))
+            .expect("Failed to send an item");
+        request
+            .send(ServerListing(1))
+            .expect("Failed to send an item");
+        // Written to the socket, a disconnect drops what wasn't
+        for _ in 0..10 {
+            peers.runtime.run_until_stalled();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let client = peers
+            .net(true)
+            .connection_ids()
+            .next()
+            .expect("Not connected");
+        peers
+            .net(true)
+            .disconnect(client)
+            .expect("Failed to disconnect");
+
+        // The items and the disconnect are read in the same frame of the client
+        for _ in 0..10 {
+            peers.runtime.run_until_stalled();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        peers.client.update();
+        let events = peers.client.world().resource::<Events<NetworkEvent>>();
+        assert!(events
+            .get_reader()
+            .read(events)
+            .any(|event| matches!(event, NetworkEvent::Disconnected(_))));
+
+        assert!(!stream.is_finished());
+        assert_eq!(stream.try_next(), Some(ServerListing(0)));
+        assert_eq!(stream.try_next(), Some(ServerListing(1)));
+        assert!(stream.is_finished());
+    }
+}
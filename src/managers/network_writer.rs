@@ -0,0 +1,154 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::system::SystemParam,
+    prelude::{error, Res},
+};
+
+use crate::{
+    connection::ConnectionTarget, error::NetworkError, labelled::NetworkEventWriter, ConnectionId,
+    NetworkEvent, NetworkMessage, NetworkPacket,
+};
+
+use super::{Network, NetworkProvider};
+
+/// A message queued by a [`NetworkWriter`], sent by [`flush_outgoing_messages`]
+pub(crate) struct OutgoingMessage {
+    /// [`None`] means all connections
+    target: Option<ConnectionId>,
+    kind: &'static str,
+    data: Vec<u8>,
+}
+
+#[derive(SystemParam)]
+/// Queues [`NetworkMessage`]s to be sent, like an [`EventWriter`](bevy::prelude::EventWriter) does for events.
+///
+/// The queued messages are sent once per frame in [`EventworkSet::Send`](crate::EventworkSet::Send),
+/// errors are reported as [`NetworkEvent::Error`] instead of being returned.
+///
+/// ```rust
+/// use bevy::prelude::*;
+/// use bevy_eventwork::{ConnectionId, NetworkMessage, managers::network_writer::NetworkWriter, tcp::TcpProvider};
+/// use serde::{Serialize, Deserialize};
+///
+/// #[derive(Serialize, Deserialize, Clone)]
+/// struct Ping;
+///
+/// impl NetworkMessage for Ping {
+///     const NAME: &'static str = "example:Ping";
+/// }
+///
+/// fn ping_everyone(mut writer: NetworkWriter<Ping, TcpProvider>) {
+///     writer.broadcast(Ping);
+/// }
+/// ```
+pub struct NetworkWriter<'w, 's, T: NetworkMessage, NP: NetworkProvider> {
+    server: Res<'w, Network<NP>>,
+    #[system_param(ignore)]
+    marker: PhantomData<(&'s usize, T)>,
+}
+
+impl<'w, 's, T: NetworkMessage, NP: NetworkProvider> NetworkWriter<'w, 's, T, NP> {
+    /// Queue a message for a specific client, addressed by its [`ConnectionId`] or the [`Entity`](bevy::prelude::Entity) of its [`NetworkConnection`](crate::NetworkConnection)
+    pub fn send(&mut self, client: impl ConnectionTarget, message: T) {
+        match client.connection_id(&self.server) {
+            Ok(conn_id) => self.send_to(conn_id, message),
+            Err(err) => self.report(err),
+        }
+    }
+
+    /// Queue a message for all connected clients
+    ///
+    /// The message is only serialized once, no matter how many clients it is sent to.
+    pub fn broadcast(&mut self, message: T) {
+        self.queue(None, &message);
+        if let (Some(_), Some(server)) = (self.server.local_client(), self.server.local_server()) {
+            self.server.send_local(server, message);
+        }
+    }
+
+    /// Queue many messages, each for a specific client
+    pub fn send_batch(&mut self, messages: impl IntoIterator<Item = (ConnectionId, T)>) {
+        for (conn_id, message) in messages {
+            self.send_to(conn_id, message);
+        }
+    }
+
+    fn send_to(&self, conn_id: ConnectionId, message: T) {
+        // The local client of `Network::connect_local` gets the message as is
+        match self.server.local_source(conn_id) {
+            Some(source) => self.server.send_local(source, message),
+            None => self.queue(Some(conn_id), &message),
+        }
+    }
+
+    fn queue(&self, target: Option<ConnectionId>, message: &T) {
+        let data = match bincode::serialize(message) {
+            Ok(data) => data,
+            Err(err) => {
+                error!("Could not serialize {}: {}", T::NAME, err);
+                self.report(NetworkError::Serialization);
+                return;
+            }
+        };
+
+        // The receiver lives as long as the network, so this can't fail
+        let _ = self
+            .server
+            .outgoing_messages
+            .sender
+            .try_send(OutgoingMessage {
+                target,
+                kind: T::NAME,
+                data,
+            });
+    }
+
+    fn report(&self, err: NetworkError) {
+        let _ = self.server.error_channel.sender.try_send(err);
+    }
+}
+
+/// Sends the messages queued by [`NetworkWriter`]s
+pub(crate) fn flush_outgoing_messages<NP: NetworkProvider>(
+    server: Res<Network<NP>>,
+    mut network_events: NetworkEventWriter<NP>,
+) {
+    while let Ok(message) = server.outgoing_messages.receiver.try_recv() {
+        match message.target {
+            Some(conn_id) => {
+                let Some(connection) = server.established_connections.get(&conn_id) else {
+                    network_events.send(NetworkEvent::Error(NetworkError::ConnectionNotFound(
+                        conn_id,
+                    )));
+                    continue;
+                };
+                let packet = NetworkPacket {
+                    kind: String::from(message.kind),
+                    data: message.data,
+                };
+                if connection.send_message.try_send(packet).is_err() {
+                    network_events.send(NetworkEvent::Error(NetworkError::ChannelClosed(conn_id)));
+                }
+            }
+            None => {
+                // The local client already got it from `NetworkWriter::broadcast`
+                for connection in server
+                    .established_connections
+                    .iter()
+                    .filter(|connection| !server.is_local(*connection.key()))
+                {
+                    let packet = NetworkPacket {
+                        kind: String::from(message.kind),
+                        data: message.data.clone(),
+                    };
+                    if connection.send_message.try_send(packet).is_err() {
+                        network_events.send(NetworkEvent::Error(NetworkError::ChannelClosed(
+                            *connection.key(),
+                        )));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! # Message permissions
+//!
+//! By default every registered message is accepted from every connection. To accept some messages only from some
+//! connections, tag the connections, for example with their role or the phase of the game they are in,
+//! and restrict the messages to those tags with [`AppNetworkPermissions::restrict_message`].
+//!
+//! A restricted message is only accepted from connections with at least one of its tags, all other messages stay unrestricted.
+//! Messages from other connections are dropped and counted in [`ConnectionStats::messages_rejected`](crate::connection::ConnectionStats::messages_rejected),
+//! and with [`ViolationPolicy::Disconnect`] the connection is dropped as well.
+//!
+//! Tags and restrictions live in the [`MessagePermissions`] resource and can be changed at any time.
+//! Connections lose their tags once they disconnect.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkEvent, NetworkMessage,
+//!     tcp::TcpProvider,
+//!     permissions::{AppNetworkPermissions, MessagePermissions, ViolationPolicy},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct PickCharacter(u32);
+//!
+//! impl NetworkMessage for PickCharacter {
+//!     const NAME: &'static str = "example:PickCharacter";
+//! }
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct KickPlayer(u32);
+//!
+//! impl NetworkMessage for KickPlayer {
+//!     const NAME: &'static str = "example:KickPlayer";
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     app.restrict_message::<PickCharacter, TcpProvider>(["lobby"]);
+//!     app.restrict_message::<KickPlayer, TcpProvider>(["admin"]);
+//!     app.on_message_violation::<TcpProvider>(ViolationPolicy::Disconnect);
+//!     app.add_systems(Update, enter_lobby);
+//! }
+//!
+//! fn enter_lobby(
+//!     mut network_events: EventReader<NetworkEvent>,
+//!     mut permissions: ResMut<MessagePermissions<TcpProvider>>,
+//! ) {
+//!     for event in network_events.read() {
+//!         if let NetworkEvent::Connected(conn_id) = event {
+//!             permissions.tag(*conn_id, "lobby");
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use bevy::prelude::{debug, warn, App, IntoSystemConfigs, ResMut, Resource};
+
+use crate::{
+    labelled::NetworkEventReader,
+    managers::{network_request::RequestMessage, Network, NetworkProvider},
+    ConnectionId, EventworkSchedules, EventworkSet, NetworkEvent, NetworkMessage,
+};
+
+/// What happens to a connection sending a message it isn't allowed to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViolationPolicy {
+    /// Drop the message
+    #[default]
+    Drop,
+    /// Drop the message and disconnect the connection
+    Disconnect,
+}
+
+/// The tags of the connections of a [`Network`] and the messages restricted to them
+#[derive(Resource)]
+pub struct MessagePermissions<NP: NetworkProvider> {
+    restrictions: HashMap<&'static str, HashSet<&'static str>>,
+    tags: HashMap<ConnectionId, HashSet<&'static str>>,
+    policy: ViolationPolicy,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for MessagePermissions<NP> {
+    fn default() -> Self {
+        Self {
+            restrictions: HashMap::new(),
+            tags: HashMap::new(),
+            policy: ViolationPolicy::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> MessagePermissions<NP> {
+    /// Tag a connection, returns false if it already had the tag
+    pub fn tag(&mut self, conn_id: ConnectionId, tag: &'static str) -> bool {
+        self.tags.entry(conn_id).or_default().insert(tag)
+    }
+
+    /// Remove a tag from a connection, returns false if it didn't have the tag
+    pub fn untag(&mut self, conn_id: ConnectionId, tag: &'static str) -> bool {
+        let Some(tags) = self.tags.get_mut(&conn_id) else {
+            return false;
+        };
+        let removed = tags.remove(tag);
+        if tags.is_empty() {
+            self.tags.remove(&conn_id);
+        }
+        removed
+    }
+
+    /// Remove all tags from a connection
+    pub fn clear_tags(&mut self, conn_id: ConnectionId) {
+        self.tags.remove(&conn_id);
+    }
+
+    /// Returns true if the connection has the tag
+    pub fn has_tag(&self, conn_id: ConnectionId, tag: &'static str) -> bool {
+        self.tags
+            .get(&conn_id)
+            .is_some_and(|tags| tags.contains(tag))
+    }
+
+    /// The tags of a connection
+    pub fn tags_of(&self, conn_id: ConnectionId) -> impl Iterator<Item = &'static str> + '_ {
+        self.tags.get(&conn_id).into_iter().flatten().copied()
+    }
+
+    /// Also accept messages of the given kind from connections with the tag, which restricts the kind if it wasn't already
+    ///
+    /// The kind is the [`NetworkMessage::NAME`] of a message, or the [`RequestMessage::REQUEST_NAME`] of a request.
+    pub fn allow(&mut self, kind: &'static str, tag: &'static str) {
+        self.restrictions.entry(kind).or_default().insert(tag);
+    }
+
+    /// Accept messages of the given kind from every connection again
+    pub fn unrestrict(&mut self, kind: &'static str) {
+        self.restrictions.remove(kind);
+    }
+
+    /// Returns true if the connection may send messages of the given kind
+    pub fn is_allowed(&self, conn_id: ConnectionId, kind: &str) -> bool {
+        let Some(allowed) = self.restrictions.get(kind) else {
+            return true;
+        };
+        self.tags
+            .get(&conn_id)
+            .is_some_and(|tags| !tags.is_disjoint(allowed))
+    }
+
+    /// What happens to connections sending messages they aren't allowed to send
+    pub fn violation_policy(&self) -> ViolationPolicy {
+        self.policy
+    }
+
+    /// Change what happens to connections sending messages they aren't allowed to send
+    pub fn set_violation_policy(&mut self, policy: ViolationPolicy) {
+        self.policy = policy;
+    }
+
+    /// Returns true if the message may be delivered, and otherwise counts the rejection.
+    /// With [`ViolationPolicy::Disconnect`], the connection is added to the violators the caller disconnects.
+    pub(crate) fn check(
+        &self,
+        net: &Network<NP>,
+        conn_id: ConnectionId,
+        kind: &str,
+        violators: &mut Vec<ConnectionId>,
+    ) -> bool {
+        if self.is_allowed(conn_id, kind) {
+            return true;
+        }
+
+        warn!("{} isn't allowed to send {}, dropping it", conn_id, kind);
+        if let Some(stats) = net.connection_stats(conn_id) {
+            stats.record_rejected();
+        }
+        if self.policy == ViolationPolicy::Disconnect && !violators.contains(&conn_id) {
+            violators.push(conn_id);
+        }
+        false
+    }
+}
+
+/// A utility trait on [`App`] to only accept some messages from some connections
+pub trait AppNetworkPermissions {
+    /// Only accept the message from connections with one of the tags, see the [module documentation](self)
+    ///
+    /// ## Details
+    /// This will:
+    /// - Add the [`MessagePermissions<NP>`] resource
+    /// - Allow the message for the tags
+    /// - Remove the tags of connections once they disconnect
+    fn restrict_message<T: NetworkMessage, NP: NetworkProvider>(
+        &mut self,
+        tags: impl IntoIterator<Item = &'static str>,
+    ) -> &mut Self;
+
+    /// Only accept the request from connections with one of the tags, like [`AppNetworkPermissions::restrict_message`]
+    fn restrict_request<T: RequestMessage, NP: NetworkProvider>(
+        &mut self,
+        tags: impl IntoIterator<Item = &'static str>,
+    ) -> &mut Self;
+
+    /// Change what happens to connections sending messages they aren't allowed to send, [`ViolationPolicy::Drop`] by default
+    fn on_message_violation<NP: NetworkProvider>(&mut self, policy: ViolationPolicy) -> &mut Self;
+}
+
+impl AppNetworkPermissions for App {
+    fn restrict_message<T: NetworkMessage, NP: NetworkProvider>(
+        &mut self,
+        tags: impl IntoIterator<Item = &'static str>,
+    ) -> &mut Self {
+        restrict_kind::<NP>(self, T::NAME, tags);
+        self
+    }
+
+    fn restrict_request<T: RequestMessage, NP: NetworkProvider>(
+        &mut self,
+        tags: impl IntoIterator<Item = &'static str>,
+    ) -> &mut Self {
+        restrict_kind::<NP>(self, T::REQUEST_NAME, tags);
+        self
+    }
+
+    fn on_message_violation<NP: NetworkProvider>(&mut self, policy: ViolationPolicy) -> &mut Self {
+        add_permissions::<NP>(self);
+        self.world_mut()
+            .resource_mut::<MessagePermissions<NP>>()
+            .set_violation_policy(policy);
+        self
+    }
+}
+
+fn restrict_kind<NP: NetworkProvider>(
+    app: &mut App,
+    kind: &'static str,
+    tags: impl IntoIterator<Item = &'static str>,
+) {
+    debug!("Restricted a message: {}", kind);
+
+    add_permissions::<NP>(app);
+    let mut permissions = app.world_mut().resource_mut::<MessagePermissions<NP>>();
+    for tag in tags {
+        permissions.allow(kind, tag);
+    }
+}
+
+/// Adds the [`MessagePermissions`] of the network, if they weren't already
+fn add_permissions<NP: NetworkProvider>(app: &mut App) {
+    if app.world().contains_resource::<MessagePermissions<NP>>() {
+        return;
+    }
+
+    let schedules = EventworkSchedules::of::<NP>(app);
+    app.init_resource::<MessagePermissions<NP>>();
+    app.add_systems(
+        schedules.receive,
+        clear_tags_on_disconnect::<NP>.in_set(EventworkSet::Receive),
+    );
+}
+
+fn clear_tags_on_disconnect<NP: NetworkProvider>(
+    mut network_events: NetworkEventReader<NP>,
+    mut permissions: ResMut<MessagePermissions<NP>>,
+) {
+    for event in network_events.read() {
+        if let NetworkEvent::Disconnected(conn_id) = event {
+            permissions.clear_tags(*conn_id);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! # Rate limiting
+//!
+//! Every packet a connection sends is decoded and kept until the next run of the receive schedule,
+//! so a client flooding the network can make the app slow down or run out of memory.
+//! With [`AppNetworkRateLimits::limit_rates`], every connection gets token buckets limiting how many packets it may send,
+//! for all messages together and for single kinds of messages. Packets over the limit are dropped right away,
+//! counted in [`ConnectionStats::packets_rate_limited`](crate::connection::ConnectionStats::packets_rate_limited),
+//! and depending on the [`RateLimitPolicy`] reported or punished with a disconnect.
+//!
+//! The limits can be changed at any time with [`Network::set_rate_limits`](crate::Network::set_rate_limits),
+//! the changes apply to established connections too. Tokens are refilled in the time of the runtime's [`Clock`].
+//! Messages exchanged by an [authenticator](crate::authentication) count towards the limits as well, a connection kicked
+//! while authenticating fails to authenticate. The local client of [`Network::connect_local`](crate::Network::connect_local)
+//! isn't limited.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkMessage,
+//!     tcp::TcpProvider,
+//!     rate_limit::{AppNetworkRateLimits, RateLimit, RateLimitPolicy, RateLimits},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct ChatMessage(String);
+//!
+//! impl NetworkMessage for ChatMessage {
+//!     const NAME: &'static str = "example:ChatMessage";
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     app.limit_rates::<TcpProvider>(
+//!         RateLimits::new()
+//!             .global(RateLimit::per_second(120).burst(240))
+//!             .message::<ChatMessage>(RateLimit::per_second(2).burst(5))
+//!             .policy(RateLimitPolicy::Warn),
+//!     );
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use async_channel::Sender;
+use bevy::prelude::{debug, warn, App};
+
+use crate::{
+    connection::ConnectionStats,
+    error::NetworkError,
+    managers::{network_request::RequestMessage, Network, NetworkProvider},
+    Clock, ConnectionId, NetworkMessage,
+};
+
+/// A token bucket, refilling with a steady rate up to its burst
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Allow the given amount of packets per second, with a burst of as many
+    pub fn per_second(packets: u32) -> Self {
+        Self {
+            per_second: f64::from(packets),
+            burst: packets.max(1),
+        }
+    }
+
+    /// Allow up to this many packets at once, at least one
+    pub fn burst(mut self, packets: u32) -> Self {
+        self.burst = packets.max(1);
+        self
+    }
+}
+
+/// What happens to a connection sending more packets than its limits allow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitPolicy {
+    /// Drop the packets over the limit
+    #[default]
+    Drop,
+    /// Drop the packets over the limit, and send a [`NetworkEvent::Error`](crate::NetworkEvent::Error)
+    /// with a [`NetworkError::RateLimited`] whenever a connection starts exceeding a limit
+    Warn,
+    /// Disconnect the connection once it exceeds a limit
+    Disconnect,
+}
+
+/// The rate limits of every connection of a network, see the [module documentation](self)
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    global: Option<RateLimit>,
+    kinds: HashMap<&'static str, RateLimit>,
+    policy: RateLimitPolicy,
+}
+
+impl RateLimits {
+    /// No limits yet, dropping packets over them
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit all packets of a connection together
+    pub fn global(mut self, limit: RateLimit) -> Self {
+        self.global = Some(limit);
+        self
+    }
+
+    /// Limit the packets of a message
+    pub fn message<T: NetworkMessage>(self, limit: RateLimit) -> Self {
+        self.kind(T::NAME, limit)
+    }
+
+    /// Limit the packets of a request
+    pub fn request<T: RequestMessage>(self, limit: RateLimit) -> Self {
+        self.kind(T::REQUEST_NAME, limit)
+    }
+
+    /// Limit the packets of the given kind, the [`NetworkMessage::NAME`] of a message
+    pub fn kind(mut self, kind: &'static str, limit: RateLimit) -> Self {
+        self.kinds.insert(kind, limit);
+        self
+    }
+
+    /// What happens to connections exceeding their limits
+    pub fn policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// The [`RateLimits`] of a network, shared with the limiters of its connections so changes apply to all of them
+pub(crate) type SharedRateLimits = Arc<RwLock<Option<RateLimits>>>;
+
+/// The buckets of a single connection
+pub(crate) struct RateLimiter {
+    conn_id: ConnectionId,
+    stats: Arc<ConnectionStats>,
+    errors: Sender<NetworkError>,
+    kicked_connections: Sender<ConnectionId>,
+    limits: SharedRateLimits,
+    clock: Clock,
+    global: Option<TokenBucket>,
+    kinds: HashMap<&'static str, TokenBucket>,
+}
+
+/// What to do with a received packet
+pub(crate) enum Admission {
+    Accept,
+    Drop,
+    /// Drop it and all packets after it, the connection is being disconnected
+    Disconnect,
+}
+
+impl RateLimiter {
+    /// The buckets for a new connection, filled up to their bursts
+    pub(crate) fn new(
+        conn_id: ConnectionId,
+        stats: Arc<ConnectionStats>,
+        errors: Sender<NetworkError>,
+        kicked_connections: Sender<ConnectionId>,
+        limits: SharedRateLimits,
+        clock: Clock,
+    ) -> Self {
+        Self {
+            conn_id,
+            stats,
+            errors,
+            kicked_connections,
+            limits,
+            clock,
+            global: None,
+            kinds: HashMap::new(),
+        }
+    }
+
+    /// Takes a token for the packet, and applies the [`RateLimitPolicy`] if there is none
+    pub(crate) fn admit(&mut self, kind: &str) -> Admission {
+        let limits = self
+            .limits
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(limits) = limits.as_ref() else {
+            return Admission::Accept;
+        };
+        let now = self.clock.now();
+
+        let kind_bucket = limits.kinds.get_key_value(kind).map(|(kind, limit)| {
+            let bucket = self
+                .kinds
+                .entry(*kind)
+                .or_insert_with(|| TokenBucket::new(*limit, now));
+            bucket.refill(*limit, now);
+            bucket
+        });
+        let global_bucket = match limits.global {
+            Some(limit) => {
+                let bucket = self
+                    .global
+                    .get_or_insert_with(|| TokenBucket::new(limit, now));
+                bucket.refill(limit, now);
+                Some(bucket)
+            }
+            None => None,
+        };
+
+        // Only take tokens if every bucket has one, so dropped packets don't use up any limit
+        let mut buckets = [kind_bucket, global_bucket];
+        let Some(bucket) = buckets
+            .iter_mut()
+            .flatten()
+            .find(|bucket| !bucket.has_token())
+        else {
+            buckets.into_iter().flatten().for_each(TokenBucket::take);
+            return Admission::Accept;
+        };
+        let first = !std::mem::replace(&mut bucket.exceeded, true);
+
+        self.stats.record_rate_limited();
+        match limits.policy {
+            RateLimitPolicy::Drop => Admission::Drop,
+            RateLimitPolicy::Warn => {
+                if first {
+                    warn!("{} exceeded its rate limit for {}", self.conn_id, kind);
+                    // Fails if the network was dropped, then nobody is listening anymore
+                    let _ = self
+                        .errors
+                        .try_send(NetworkError::RateLimited(self.conn_id, String::from(kind)));
+                }
+                Admission::Drop
+            }
+            RateLimitPolicy::Disconnect => {
+                warn!(
+                    "{} exceeded its rate limit for {}, disconnecting it",
+                    self.conn_id, kind
+                );
+                let _ = self.kicked_connections.try_send(self.conn_id);
+                Admission::Disconnect
+            }
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    /// In the time of the limiter's [`Clock`]
+    refilled_at: Duration,
+    /// True while packets are dropped, to only warn once per flood
+    exceeded: bool,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit, now: Duration) -> Self {
+        Self {
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+            exceeded: false,
+        }
+    }
+
+    /// Adds the tokens of the time passed since the last refill, with the current limit
+    fn refill(&mut self, limit: RateLimit, now: Duration) {
+        let elapsed = now.saturating_sub(self.refilled_at);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * limit.per_second).min(f64::from(limit.burst));
+        self.refilled_at = now;
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+        self.exceeded = false;
+    }
+}
+
+impl<NP: NetworkProvider> Network<NP> {
+    /// The current rate limits of the connections, see the [`rate_limit`](crate::rate_limit) module
+    pub fn rate_limits(&self) -> Option<RateLimits> {
+        self.rate_limits
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Change the rate limits of new and established connections, [`None`] to stop limiting them
+    pub fn set_rate_limits(&self, limits: Option<RateLimits>) {
+        *self
+            .rate_limits
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = limits;
+    }
+}
+
+/// A utility trait on [`App`] to limit how many packets connections may send
+pub trait AppNetworkRateLimits {
+    /// Limit the packets every connection of the network may send, replacing earlier limits
+    fn limit_rates<NP: NetworkProvider>(&mut self, limits: RateLimits) -> &mut Self;
+}
+
+impl AppNetworkRateLimits for App {
+    fn limit_rates<NP: NetworkProvider>(&mut self, limits: RateLimits) -> &mut Self {
+        let network = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before limiting rates.");
+
+        debug!("Limiting the rates of connections");
+
+        network.set_rate_limits(Some(limits));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Runtime, SimulatedRuntime};
+
+    fn limiter(limits: &SharedRateLimits, runtime: &SimulatedRuntime) -> RateLimiter {
+        RateLimiter::new(
+            ConnectionId { id: 0 },
+            Arc::new(ConnectionStats::default()),
+            async_channel::unbounded().0,
+            async_channel::unbounded().0,
+            limits.clone(),
+            runtime.clock(),
+        )
+    }
+
+    #[test]
+    fn refills_on_the_clock() {
+        let runtime = SimulatedRuntime::new();
+        let limits = SharedRateLimits::default();
+        *limits.write().expect("Poisoned limits") =
+            Some(RateLimits::new().global(RateLimit::per_second(1)));
+        let mut limiter = limiter(&limits, &runtime);
+
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+        assert!(matches!(limiter.admit("chat"), Admission::Drop));
+        runtime.advance(Duration::from_millis(999));
+        assert!(matches!(limiter.admit("chat"), Admission::Drop));
+        runtime.advance(Duration::from_millis(1));
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+    }
+
+    #[test]
+    fn dropped_packets_take_no_tokens() {
+        let runtime = SimulatedRuntime::new();
+        let limits = SharedRateLimits::default();
+        *limits.write().expect("Poisoned limits") = Some(
+            RateLimits::new()
+                .global(RateLimit::per_second(1))
+                .kind("chat", RateLimit::per_second(1).burst(2)),
+        );
+        let mut limiter = limiter(&limits, &runtime);
+
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+        // Over the global limit, the kind keeps its token
+        assert!(matches!(limiter.admit("chat"), Admission::Drop));
+        assert_eq!(limiter.kinds["chat"].tokens, 1.0);
+    }
+
+    #[test]
+    fn applies_changed_limits() {
+        let runtime = SimulatedRuntime::new();
+        let limits = SharedRateLimits::default();
+        let mut limiter = limiter(&limits, &runtime);
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+
+        *limits.write().expect("Poisoned limits") = Some(
+            RateLimits::new()
+                .kind("chat", RateLimit::per_second(1))
+                .policy(RateLimitPolicy::Disconnect),
+        );
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+        assert!(matches!(limiter.admit("chat"), Admission::Disconnect));
+
+        *limits.write().expect("Poisoned limits") = None;
+        assert!(matches!(limiter.admit("chat"), Admission::Accept));
+    }
+}
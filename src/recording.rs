@@ -0,0 +1,592 @@
+//! # Traffic recording and replay
+//!
+//! To reproduce what a player saw, a [`Network`] can record its traffic with [`Network::record_traffic`]:
+//! every packet received and sent, and every connection and disconnection, together with the frame and time it happened at.
+//!
+//! A recording is replayed by inserting a [`TrafficReplay`] into an app, usually a headless one using the [`ReplayProvider`].
+//! The replay fires the same [`NetworkEvent`](crate::NetworkEvent)s and [`NetworkData`](crate::NetworkData) events
+//! in the same frames they were fired in while recording, counting frames from the first run of the receive schedule.
+//! Requests, replication and everything else built on messages is replayed with them, as long as the replaying app
+//! registers the same messages. Sent packets are only recorded for inspection, they aren't replayed.
+//!
+//! Messages sent to the local client of [`Network::connect_local`] without serializing them aren't recorded.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     AppNetworkMessage, EventworkPlugin, EventworkRuntime, Network, NetworkMessage,
+//!     recording::{ReplayProvider, ReplaySettings, TrafficReplay},
+//!     tcp::TcpProvider,
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct PlayerInput;
+//!
+//! impl NetworkMessage for PlayerInput {
+//!     const NAME: &'static str = "example:PlayerInput";
+//! }
+//!
+//! // In the game
+//! fn start_recording(net: Res<Network<TcpProvider>>) {
+//!     net.record_traffic_to_file("session.traffic").expect("Could not create recording");
+//! }
+//!
+//! // In a headless app, to replay the session
+//! fn replay() {
+//!     let mut app = App::new();
+//!     app.add_plugins(MinimalPlugins);
+//!     app.add_plugins(EventworkPlugin::<ReplayProvider, bevy::tasks::TaskPool>::default());
+//!     app.insert_resource(EventworkRuntime(bevy::tasks::TaskPoolBuilder::new().build()));
+//!     app.insert_resource(ReplaySettings);
+//!     app.insert_resource(TrafficReplay::<ReplayProvider>::from_file("session.traffic").expect("Could not open recording"));
+//!     app.listen_for_message::<PlayerInput, ReplayProvider>();
+//!
+//!     while !app.world().resource::<TrafficReplay>().is_finished() {
+//!         app.update();
+//!     }
+//! }
+//! ```
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    marker::PhantomData,
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use async_channel::{unbounded, Receiver, Sender};
+use async_trait::async_trait;
+use bevy::{prelude::*, utils::Instant};
+use futures_lite::stream::Empty;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::NetworkError, managers::NetworkProvider, ConnectionId, Network, NetworkPacket};
+
+/// Something that happened on a connection, see [`TrafficRecord`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TrafficEvent {
+    /// The connection was established
+    Connected,
+    /// The connection was lost or closed
+    Disconnected,
+    /// A packet was received and handed to the message's events
+    Received(NetworkPacket),
+    /// A packet was handed to the provider to be sent
+    Sent(NetworkPacket),
+}
+
+/// One entry of a traffic recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficRecord {
+    /// The runs of the receive schedule since the recording started
+    pub frame: u64,
+    /// The time since the recording started
+    pub time: Duration,
+    /// The connection it happened on
+    pub conn_id: ConnectionId,
+    /// What happened
+    pub event: TrafficEvent,
+}
+
+/// Writes the traffic of a [`Network`] while a recording is running
+#[derive(Default)]
+pub(crate) struct TrafficRecorder {
+    /// Lets connections skip the lock while nothing is recorded
+    active: AtomicBool,
+    recording: Mutex<Option<Recording>>,
+}
+
+struct Recording {
+    writer: Box<dyn Write + Send>,
+    started: Instant,
+    /// [`None`] until the receive schedule ran the first time
+    frame: Option<u64>,
+}
+
+impl TrafficRecorder {
+    pub(crate) fn start(&self, writer: Box<dyn Write + Send>) {
+        *self.lock() = Some(Recording {
+            writer,
+            started: Instant::now(),
+            frame: None,
+        });
+        self.active.store(true, Ordering::Relaxed);
+    }
+
+    pub(crate) fn stop(&self) {
+        self.active.store(false, Ordering::Relaxed);
+        if let Some(mut recording) = self.lock().take() {
+            if let Err(err) = recording.writer.flush() {
+                error!("Could not finish traffic recording: {}", err);
+            }
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Only clones the packet if a recording is running
+    pub(crate) fn record_packet(
+        &self,
+        conn_id: ConnectionId,
+        packet: &NetworkPacket,
+        event: fn(NetworkPacket) -> TrafficEvent,
+    ) {
+        if self.is_active() {
+            self.record(conn_id, event(packet.clone()));
+        }
+    }
+
+    pub(crate) fn record(&self, conn_id: ConnectionId, event: TrafficEvent) {
+        if !self.is_active() {
+            return;
+        }
+
+        let mut recording = self.lock();
+        let Some(Recording {
+            writer,
+            started,
+            frame,
+        }) = recording.as_mut()
+        else {
+            return;
+        };
+        let record = TrafficRecord {
+            frame: frame.unwrap_or_default(),
+            time: started.elapsed(),
+            conn_id,
+            event,
+        };
+        if let Err(err) = bincode::serialize_into(writer, &record) {
+            error!("Could not write traffic recording, stopping it: {}", err);
+            self.active.store(false, Ordering::Relaxed);
+            recording.take();
+        }
+    }
+
+    /// Called at the start of every run of the receive schedule
+    fn next_frame(&self) {
+        if !self.is_active() {
+            return;
+        }
+
+        let mut recording = self.lock();
+        if let Some(state) = recording.as_mut() {
+            state.frame = Some(state.frame.map_or(0, |frame| frame + 1));
+            // Keep the file usable even if the app is killed
+            if let Err(err) = state.writer.flush() {
+                error!("Could not write traffic recording, stopping it: {}", err);
+                self.active.store(false, Ordering::Relaxed);
+                recording.take();
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Recording>> {
+        self.recording
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<NP: NetworkProvider> Network<NP> {
+    /// Record the traffic of all connections to the writer, until [`Network::stop_recording`] is called
+    ///
+    /// A recording that is already running is stopped first. See the [`recording`](crate::recording) module on how to replay it.
+    pub fn record_traffic(&self, writer: impl Write + Send + 'static) {
+        self.recorder.stop();
+        self.recorder.start(Box::new(writer));
+    }
+
+    /// Record the traffic of all connections to a new file at the path, see [`Network::record_traffic`]
+    pub fn record_traffic_to_file(&self, path: impl AsRef<Path>) -> Result<(), NetworkError> {
+        let file = File::create(path).map_err(NetworkError::Recording)?;
+        self.record_traffic(BufWriter::new(file));
+        Ok(())
+    }
+
+    /// Stop recording and flush the recording
+    pub fn stop_recording(&self) {
+        self.recorder.stop();
+    }
+
+    /// Returns true while the traffic is recorded
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_active()
+    }
+}
+
+/// Replays a traffic recording into the [`Network`] of `NP`, see the [module documentation](self)
+///
+/// The replay starts with the next run of the receive schedule after inserting it.
+#[derive(Resource)]
+pub struct TrafficReplay<NP: NetworkProvider = ReplayProvider> {
+    reader: Box<dyn Read + Send + Sync>,
+    next: Option<TrafficRecord>,
+    frame: u64,
+    /// Messages sent to replayed connections go nowhere
+    outgoing: HashMap<ConnectionId, Receiver<NetworkPacket>>,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> TrafficReplay<NP> {
+    /// Replay the recording read from the reader
+    pub fn new(reader: impl Read + Send + Sync + 'static) -> Self {
+        let mut replay = Self {
+            reader: Box::new(reader),
+            next: None,
+            frame: 0,
+            outgoing: HashMap::new(),
+            marker: PhantomData,
+        };
+        replay.next = replay.read_record();
+        replay
+    }
+
+    /// Replay the recording in the file at the path
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        let file = File::open(path).map_err(NetworkError::Recording)?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+
+    /// The frame of the recording that is replayed next
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Returns true once everything in the recording was replayed
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    fn read_record(&mut self) -> Option<TrafficRecord> {
+        match bincode::deserialize_from(&mut self.reader) {
+            Ok(record) => Some(record),
+            Err(err) => {
+                if !matches!(&*err, bincode::ErrorKind::Io(err) if err.kind() == ErrorKind::UnexpectedEof)
+                {
+                    error!(
+                        "Could not read traffic recording, ending the replay: {}",
+                        err
+                    );
+                }
+                None
+            }
+        }
+    }
+
+    fn replay_frame(&mut self, net: &Network<NP>) {
+        loop {
+            let record = match self.next.take() {
+                Some(record) if record.frame <= self.frame => record,
+                later => {
+                    // Belongs to a later frame, or the recording is over
+                    self.next = later;
+                    break;
+                }
+            };
+            let conn_id = record.conn_id;
+            match record.event {
+                TrafficEvent::Connected => {
+                    let (outgoing_tx, outgoing_rx) = unbounded();
+                    net.add_replayed_connection(conn_id, outgoing_tx);
+                    self.outgoing.insert(conn_id, outgoing_rx);
+                }
+                TrafficEvent::Disconnected => {
+                    self.outgoing.remove(&conn_id);
+                    if let Err(err) = net.disconnect(conn_id) {
+                        warn!("Could not replay disconnect: {}", err);
+                    }
+                }
+                TrafficEvent::Received(packet) => {
+                    match net.recv_message_map.get_mut(&packet.kind[..]) {
+                        Some(mut packets) => packets.push((conn_id, packet.data)),
+                        None => {
+                            error!(
+                                "Could not find existing entries for message kinds: {:?}",
+                                packet
+                            );
+                        }
+                    }
+                }
+                TrafficEvent::Sent(_) => (),
+            }
+            self.next = self.read_record();
+        }
+
+        for outgoing in self.outgoing.values() {
+            while outgoing.try_recv().is_ok() {}
+        }
+        self.frame += 1;
+    }
+}
+
+/// Starts the next frame of recordings and replays, before any connection is handled
+pub(crate) fn advance_traffic_frame<NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    replay: Option<ResMut<TrafficReplay<NP>>>,
+) {
+    net.recorder.next_frame();
+    if let Some(mut replay) = replay {
+        replay.replay_frame(&net);
+    }
+}
+
+/// A [`NetworkProvider`] without sockets, for apps that only replay recordings
+///
+/// Listening and connecting fail with a [`NetworkError::Error`].
+#[derive(Default, Debug)]
+pub struct ReplayProvider;
+
+/// The settings of the [`ReplayProvider`], there is nothing to configure
+#[derive(Resource, Default, Debug, Clone, Copy)]
+pub struct ReplaySettings;
+
+/// The socket of the [`ReplayProvider`], which never makes any
+#[derive(Debug)]
+pub enum ReplaySocket {}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl NetworkProvider for ReplayProvider {
+    type NetworkSettings = ReplaySettings;
+
+    type Socket = ReplaySocket;
+
+    type ReadHalf = ReplaySocket;
+
+    type WriteHalf = ReplaySocket;
+
+    type ConnectInfo = ();
+
+    type AcceptInfo = ();
+
+    type AcceptStream = Empty<ReplaySocket>;
+
+    async fn accept_loop(
+        _: Self::AcceptInfo,
+        _: Self::NetworkSettings,
+    ) -> Result<Self::AcceptStream, NetworkError> {
+        Err(NetworkError::Error(String::from(
+            "The replay provider can't listen",
+        )))
+    }
+
+    async fn connect_task(
+        _: Self::ConnectInfo,
+        _: Self::NetworkSettings,
+    ) -> Result<Self::Socket, NetworkError> {
+        Err(NetworkError::Error(String::from(
+            "The replay provider can't connect",
+        )))
+    }
+
+    async fn recv_loop(
+        read_half: Self::ReadHalf,
+        _: Sender<NetworkPacket>,
+        _: Self::NetworkSettings,
+    ) {
+        match read_half {}
+    }
+
+    async fn send_loop(
+        write_half: Self::WriteHalf,
+        _: Receiver<NetworkPacket>,
+        _: Self::NetworkSettings,
+    ) {
+        match write_half {}
+    }
+
+    fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf) {
+        match combined {}
+    }
+
+    fn peer_addr(socket: &Self::Socket) -> Option<SocketAddr> {
+        match *socket {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+    };
+
+    use bevy::ecs::event::{Events, ManualEventReader};
+
+    use super::*;
+    use crate::{
+        tcp::{NetworkSettings, TcpProvider},
+        AppNetworkMessage, EventworkPlugin, EventworkRuntime, NetworkData, NetworkEvent,
+        NetworkMessage, SimulatedRuntime,
+    };
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Chat(u32);
+
+    impl NetworkMessage for Chat {
+        const NAME: &'static str = "test:Chat";
+    }
+
+    /// A writer the test can still read from once the network is done with it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .expect("Poisoned buffer")
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// What the app saw in one frame
+    #[derive(Debug, PartialEq)]
+    enum Seen {
+        Connected(u64, ConnectionId),
+        Disconnected(u64, ConnectionId),
+        Data(u64, ConnectionId, Chat),
+    }
+
+    /// Collects what the app saw since the last call
+    struct Observer {
+        events: ManualEventReader<NetworkEvent>,
+        data: ManualEventReader<NetworkData<Chat>>,
+    }
+
+    impl Observer {
+        fn new() -> Self {
+            Self {
+                events: ManualEventReader::default(),
+                data: ManualEventReader::default(),
+            }
+        }
+
+        fn observe(&mut self, app: &App, frame: u64, seen: &mut Vec<Seen>) {
+            let events = app.world().resource::<Events<NetworkEvent>>();
+            seen.extend(self.events.read(events).filter_map(|event| match event {
+                NetworkEvent::Connected(conn_id) => Some(Seen::Connected(frame, *conn_id)),
+                NetworkEvent::Disconnected(conn_id) => Some(Seen::Disconnected(frame, *conn_id)),
+                NetworkEvent::Error(_) => None,
+            }));
+            let data = app.world().resource::<Events<NetworkData<Chat>>>();
+            seen.extend(
+                self.data
+                    .read(data)
+                    .map(|data| Seen::Data(frame, *data.source(), (**data).clone())),
+            );
+        }
+    }
+
+    fn send(client: &mut TcpStream, chat: Chat) {
+        let encoded = bincode::serialize(&NetworkPacket {
+            kind: String::from(Chat::NAME),
+            data: bincode::serialize(&chat).expect("Failed to encode"),
+        })
+        .expect("Failed to encode");
+        client
+            .write_all(&(encoded.len() as u64).to_le_bytes())
+            .and_then(|_| client.write_all(&encoded))
+            .expect("Failed to send");
+    }
+
+    #[test]
+    fn replays_the_recorded_frames() {
+        let runtime = SimulatedRuntime::new();
+        let mut app = App::new();
+        app.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+        app.insert_resource(NetworkSettings::default());
+        app.insert_resource(EventworkRuntime(runtime.clone()));
+        app.listen_for_message::<Chat, TcpProvider>();
+
+        // A free port, for the network to listen on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("No free port");
+        app.world_mut()
+            .resource_scope(|world, mut net: Mut<Network<TcpProvider>>| {
+                net.listen(addr, &runtime, world.resource::<NetworkSettings>())
+            })
+            .expect("Failed to listen");
+        // Binds the listener
+        runtime.run_until_stalled();
+
+        let buffer = SharedBuffer::default();
+        app.world()
+            .resource::<Network<TcpProvider>>()
+            .record_traffic(buffer.clone());
+
+        let mut client = Some(TcpStream::connect(addr).expect("Failed to connect"));
+        let mut recorded = Vec::new();
+        let mut observer = Observer::new();
+        for frame in 0..60 {
+            if let Some(client) = client.as_mut().filter(|_| frame % 10 == 5) {
+                send(client, Chat(frame as u32));
+                send(client, Chat(frame as u32 + 1));
+            }
+            if frame == 40 {
+                // Disconnects
+                client = None;
+            }
+            runtime.run_until_stalled();
+            app.update();
+            observer.observe(&app, frame, &mut recorded);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let net = app.world().resource::<Network<TcpProvider>>();
+        net.stop_recording();
+        assert!(!net.is_recording());
+
+        let conn_id = ConnectionId { id: 0 };
+        assert!(matches!(recorded.first(), Some(Seen::Connected(_, id)) if *id == conn_id));
+        assert!(matches!(recorded.last(), Some(Seen::Disconnected(_, id)) if *id == conn_id));
+        assert_eq!(
+            recorded
+                .iter()
+                .filter(|seen| matches!(seen, Seen::Data(..)))
+                .count(),
+            8
+        );
+
+        let recording = std::mem::take(&mut *buffer.0.lock().expect("Poisoned buffer"));
+        let mut replay = App::new();
+        replay.add_plugins(EventworkPlugin::<ReplayProvider, SimulatedRuntime>::default());
+        replay.insert_resource(ReplaySettings);
+        replay.insert_resource(EventworkRuntime(runtime.clone()));
+        replay.insert_resource(TrafficReplay::<ReplayProvider>::new(Cursor::new(recording)));
+        replay.listen_for_message::<Chat, ReplayProvider>();
+
+        let mut replayed = Vec::new();
+        let mut observer = Observer::new();
+        let mut frame = 0;
+        while !replay
+            .world()
+            .resource::<TrafficReplay<ReplayProvider>>()
+            .is_finished()
+        {
+            replay.update();
+            observer.observe(&replay, frame, &mut replayed);
+            frame += 1;
+        }
+        assert_eq!(replayed, recorded);
+    }
+}
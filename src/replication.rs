@@ -0,0 +1,794 @@
+//! # Replication
+//!
+//! Keeps ECS state of a listening app, the server, in sync on the apps connected to it, the clients.
+//!
+//! **Server**
+//!
+//! - Add the [`Replicated`] marker to every entity that should be mirrored on the clients.
+//! - Whenever a component registered with [`AppNetworkReplication::replicate`] is added or changed on such an entity,
+//!   it is sent to every client. Newly connected clients receive every replicated component once.
+//! - Removing a component, the [`Replicated`] marker, or despawning the entity, is propagated as well.
+//! - Changes are sent as deltas: the server remembers the component it last sent to each client, and only sends the bytes
+//!   of its encoding that changed since, when that is smaller than the whole component. Changes that leave the encoding
+//!   as it was aren't sent at all. Providers that aren't [`RELIABLE`](NetworkProvider::RELIABLE) always get the whole component.
+//!
+//! **Client**
+//!
+//! - The first time a server entity is seen, a local entity with a [`Replica`] component is spawned for it.
+//!   [`ReplicatedEntities`] maps server entities to these local entities. Both are kept per network.
+//! - Replicated components are inserted, updated and removed on the local entity.
+//! - The local entity is despawned when the server entity is, or when the connection to the server is lost.
+//!
+//! **Resources**
+//!
+//! Resources registered with [`AppNetworkReplication::replicate_resource`] are sent to every new client, and to all clients
+//! whenever they change or are removed on the server. Clients insert, update and remove the resource accordingly.
+//!
+//! **Visibility**
+//!
+//! Which clients replicated entities are sent to can be restricted, see [`visibility`].
+//!
+//! Both sides register the same components, an app only sends while it is listening, and only applies updates while it isn't.
+//! Entities stored inside replicated components are not mapped, use [`ReplicatedEntities::get`] for that.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     NetworkMessage,
+//!     tcp::TcpProvider,
+//!     replication::{AppNetworkReplication, Replicated},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Component, Serialize, Deserialize, Clone)]
+//! struct Position(f32, f32);
+//!
+//! impl NetworkMessage for Position {
+//!     const NAME: &'static str = "example:Position";
+//! }
+//!
+//! #[derive(Resource, Serialize, Deserialize, Clone)]
+//! struct RoundTimer(f32);
+//!
+//! impl NetworkMessage for RoundTimer {
+//!     const NAME: &'static str = "example:RoundTimer";
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     // Both the server and the client register the component and resource
+//!     app.replicate::<Position, TcpProvider>();
+//!     app.replicate_resource::<RoundTimer, TcpProvider>();
+//! }
+//!
+//! fn spawn_on_server(mut commands: Commands) {
+//!     commands.spawn((Replicated, Position(0.0, 0.0)));
+//! }
+//! ```
+
+use std::{collections::HashMap, marker::PhantomData};
+
+use bevy::prelude::{
+    debug, error, warn, App, Commands, Component, DetectChanges, Entity, IntoSystemConfigs, Local,
+    Query, Ref, RemovedComponents, Res, ResMut, Resource, With,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    labelled::{add_data_event, NetworkDataReader, NetworkEventReader},
+    managers::{network::register_message, Network, NetworkProvider},
+    ConnectionId, EventworkSet, NetworkEvent, NetworkMessage,
+};
+
+mod delta;
+use delta::{ByteDelta, Encoded, SentComponents};
+
+pub mod visibility;
+use visibility::{finish_visibility, ClientVisibility};
+
+/// How many frames updates for a despawned server entity are ignored, in case they were read late
+const DESPAWN_TOMBSTONE_FRAMES: u8 = 8;
+
+/// Marks a server entity to be mirrored on all connected clients
+#[derive(Component, Debug, Default, Clone, Copy)]
+pub struct Replicated;
+
+/// Marks a client entity that mirrors an entity of the server the network of `NP` is connected to
+#[derive(Component)]
+pub struct Replica<NP: NetworkProvider> {
+    source: ConnectionId,
+    server_entity: Entity,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Replica<NP> {
+    /// The connection the entity is replicated from
+    #[inline(always)]
+    pub fn source(&self) -> ConnectionId {
+        self.source
+    }
+
+    /// The entity on the server this entity mirrors
+    #[inline(always)]
+    pub fn server_entity(&self) -> Entity {
+        self.server_entity
+    }
+}
+
+impl<NP: NetworkProvider> Clone for Replica<NP> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<NP: NetworkProvider> Copy for Replica<NP> {}
+
+impl<NP: NetworkProvider> std::fmt::Debug for Replica<NP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Replica")
+            .field("source", &self.source)
+            .field("server_entity", &self.server_entity)
+            .finish()
+    }
+}
+
+/// Maps the server entities replicated over the network of `NP` to the local entities mirroring them
+#[derive(Resource)]
+pub struct ReplicatedEntities<NP: NetworkProvider> {
+    /// The local entity, and the tick the server entity became visible at
+    map: HashMap<Entity, (Entity, u32)>,
+    /// The frames since the despawn, and the tick the server entity became visible at
+    tombstones: HashMap<Entity, (u8, u32)>,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for ReplicatedEntities<NP> {
+    fn default() -> Self {
+        Self {
+            map: HashMap::new(),
+            tombstones: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> std::fmt::Debug for ReplicatedEntities<NP> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplicatedEntities")
+            .field("map", &self.map)
+            .field("tombstones", &self.tombstones)
+            .finish()
+    }
+}
+
+impl<NP: NetworkProvider> ReplicatedEntities<NP> {
+    /// The local entity mirroring the given server entity
+    pub fn get(&self, server_entity: Entity) -> Option<Entity> {
+        self.map.get(&server_entity).map(|(entity, _)| *entity)
+    }
+
+    /// Returns [`None`] if the update belongs to an already despawned mirror
+    fn get_or_spawn(
+        &mut self,
+        source: ConnectionId,
+        server_entity: Entity,
+        since: u32,
+        commands: &mut Commands,
+    ) -> Option<Entity> {
+        if self
+            .tombstones
+            .get(&server_entity)
+            .is_some_and(|(_, despawned)| *despawned >= since)
+        {
+            return None;
+        }
+
+        match self.map.get(&server_entity).copied() {
+            Some((_, known)) if since < known => None,
+            Some((entity, known)) if since == known => Some(entity),
+            outdated => {
+                // The entity left and re-entered visibility before the despawn was read
+                if let Some((entity, _)) = outdated {
+                    commands.entity(entity).despawn();
+                }
+                let entity = commands
+                    .spawn(Replica::<NP> {
+                        source,
+                        server_entity,
+                        marker: PhantomData,
+                    })
+                    .id();
+                self.map.insert(server_entity, (entity, since));
+                Some(entity)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ComponentReplication<C> {
+    entity: u64,
+    /// The tick the entity became visible to the receiver at, see [`ClientVisibility`]
+    since: u32,
+    update: ComponentUpdate<C>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum ComponentUpdate<C> {
+    Full(C),
+    /// The changes since the state last sent to the receiver, see [`delta`]
+    Delta(ByteDelta),
+    Removed,
+}
+
+impl<C: NetworkMessage> NetworkMessage for ComponentReplication<C> {
+    const NAME: &'static str = C::NAME;
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ResourceReplication<R> {
+    /// [`None`] means the resource was removed
+    resource: Option<R>,
+}
+
+impl<R: NetworkMessage> NetworkMessage for ResourceReplication<R> {
+    const NAME: &'static str = R::NAME;
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct EntityDespawned {
+    entity: u64,
+    since: u32,
+}
+
+impl NetworkMessage for EntityDespawned {
+    const NAME: &'static str = "eventwork:EntityDespawned";
+}
+
+/// A utility trait on [`App`] to replicate components and resources from a server to its clients
+pub trait AppNetworkReplication {
+    /// Register a component to be replicated over the given provider
+    ///
+    /// ## Details
+    /// This will:
+    /// - Send the component of every [`Replicated`] entity to new connections, and every change of it to all connections, while listening.
+    ///   Changes are sent as deltas against the component last sent to each connection, see the [module documentation](self)
+    /// - Apply received components to the matching [`Replica`] entities, while not listening
+    /// - Internal bookkeeping
+    fn replicate<C, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        C: Component + NetworkMessage + Clone;
+
+    /// Register a resource to be replicated over the given provider
+    ///
+    /// ## Details
+    /// This will:
+    /// - Send the resource to new connections, and every change or removal of it to all connections, while listening
+    /// - Insert, update and remove the received resource, while not listening
+    /// - Internal bookkeeping
+    fn replicate_resource<R, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        R: Resource + NetworkMessage + Clone;
+}
+
+impl AppNetworkReplication for App {
+    fn replicate<C, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        C: Component + NetworkMessage + Clone,
+    {
+        listen_for_despawns::<NP>(self);
+
+        let network = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before replicating components.");
+
+        debug!(
+            "Registered a new replicated component: {}",
+            ComponentReplication::<C>::NAME
+        );
+
+        assert!(
+            !network
+                .recv_message_map
+                .contains_key(ComponentReplication::<C>::NAME),
+            "Duplicate registration of replicated component: {}",
+            ComponentReplication::<C>::NAME
+        );
+        network
+            .recv_message_map
+            .insert(ComponentReplication::<C>::NAME, Vec::new());
+        let schedules = network.schedules;
+        add_data_event::<ComponentReplication<C>, NP>(self);
+        self.add_systems(
+            schedules.receive,
+            (
+                register_message::<ComponentReplication<C>, NP>,
+                apply_component_updates::<C, NP>,
+            )
+                .chain()
+                .before(apply_despawns::<NP>)
+                .in_set(EventworkSet::Receive),
+        );
+        self.add_systems(
+            schedules.send,
+            send_component_updates::<C, NP>
+                .after(finish_visibility::<NP>)
+                .before(send_despawns::<NP>)
+                .in_set(EventworkSet::Send),
+        )
+    }
+
+    fn replicate_resource<R, NP: NetworkProvider>(&mut self) -> &mut Self
+    where
+        R: Resource + NetworkMessage + Clone,
+    {
+        let network = self.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before replicating resources.");
+
+        debug!(
+            "Registered a new replicated resource: {}",
+            ResourceReplication::<R>::NAME
+        );
+
+        assert!(
+            !network
+                .recv_message_map
+                .contains_key(ResourceReplication::<R>::NAME),
+            "Duplicate registration of replicated resource: {}",
+            ResourceReplication::<R>::NAME
+        );
+        network
+            .recv_message_map
+            .insert(ResourceReplication::<R>::NAME, Vec::new());
+        let schedules = network.schedules;
+        add_data_event::<ResourceReplication<R>, NP>(self);
+        self.add_systems(
+            schedules.receive,
+            (
+                register_message::<ResourceReplication<R>, NP>,
+                apply_resource_updates::<R, NP>,
+            )
+                .chain()
+                .in_set(EventworkSet::Receive),
+        );
+        self.add_systems(
+            schedules.send,
+            send_resource_updates::<R, NP>.in_set(EventworkSet::Send),
+        )
+    }
+}
+
+/// Registers the despawn message and the entity bookkeeping for the given provider, if it hasn't been already
+fn listen_for_despawns<NP: NetworkProvider>(app: &mut App) {
+    let network = app.world().get_resource::<Network<NP>>().expect("Could not find `Network`. Be sure to include the `EventworkPlugin` before replicating components.");
+
+    if network.recv_message_map.contains_key(EntityDespawned::NAME) {
+        return;
+    }
+
+    network
+        .recv_message_map
+        .insert(EntityDespawned::NAME, Vec::new());
+    let schedules = network.schedules;
+    add_data_event::<EntityDespawned, NP>(app);
+    app.init_resource::<ReplicatedEntities<NP>>();
+    app.add_systems(
+        schedules.receive,
+        (
+            register_message::<EntityDespawned, NP>,
+            apply_despawns::<NP>,
+        )
+            .chain()
+            .in_set(EventworkSet::Receive),
+    );
+    app.add_systems(
+        schedules.send,
+        send_despawns::<NP>
+            .after(finish_visibility::<NP>)
+            .in_set(EventworkSet::Send),
+    );
+}
+
+fn send_component_updates<C, NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut network_events: NetworkEventReader<NP>,
+    components: Query<(Entity, Ref<C>, Ref<Replicated>)>,
+    (mut removed, mut unreplicated): (RemovedComponents<C>, RemovedComponents<Replicated>),
+    replicated: Query<(), With<Replicated>>,
+    visibility: Option<Res<ClientVisibility<NP>>>,
+    mut sent: Local<SentComponents>,
+) where
+    C: Component + NetworkMessage + Clone,
+{
+    if !net.is_listening() {
+        network_events.clear();
+        removed.clear();
+        unreplicated.clear();
+        return;
+    }
+
+    for entity in unreplicated.read() {
+        sent.forget_entity(entity);
+    }
+
+    let mut connected = Vec::new();
+    for event in network_events.read() {
+        match event {
+            NetworkEvent::Connected(conn_id) => {
+                sent.forget_connection(*conn_id);
+                connected.push(*conn_id);
+            }
+            NetworkEvent::Disconnected(conn_id) => sent.forget_connection(*conn_id),
+            _ => (),
+        }
+    }
+
+    if let Some(visibility) = visibility {
+        send_visible_component_updates(
+            &net,
+            &visibility,
+            components,
+            removed,
+            replicated,
+            &mut sent,
+        );
+        return;
+    }
+
+    for conn_id in connected {
+        for (entity, component, _) in components.iter() {
+            if let Some(bytes) = encode(&*component) {
+                send_component(&net, &mut sent, conn_id, entity, 0, &*component, &bytes);
+            }
+        }
+    }
+
+    for (entity, component, marker) in components.iter() {
+        // Entities replicated only now are new to the clients, even if the component didn't change
+        if marker.is_added() {
+            sent.forget_entity(entity);
+        } else if !component.is_changed() {
+            continue;
+        }
+        let Some(bytes) = encode(&*component) else {
+            continue;
+        };
+        for conn_id in net
+            .connection_ids()
+            .filter(|conn_id| !net.is_local(*conn_id))
+        {
+            send_component(&net, &mut sent, conn_id, entity, 0, &*component, &bytes);
+        }
+    }
+
+    for entity in removed.read() {
+        sent.forget_entity(entity);
+        // Despawned entities are handled by `send_despawns`
+        if replicated.contains(entity) {
+            net.broadcast_remote(ComponentReplication::<C> {
+                entity: entity.to_bits(),
+                since: 0,
+                update: ComponentUpdate::Removed,
+            });
+        }
+    }
+}
+
+/// Like [`send_component_updates`], but only sends to the connections that can see the entity
+fn send_visible_component_updates<C, NP: NetworkProvider>(
+    net: &Network<NP>,
+    visibility: &ClientVisibility<NP>,
+    components: Query<(Entity, Ref<C>, Ref<Replicated>)>,
+    mut removed: RemovedComponents<C>,
+    replicated: Query<(), With<Replicated>>,
+    sent: &mut SentComponents,
+) where
+    C: Component + NetworkMessage + Clone,
+{
+    for (conn_id, left) in visibility.left.iter() {
+        for (entity, _) in left {
+            sent.forget(*conn_id, *entity);
+        }
+    }
+
+    for (conn_id, entered) in visibility.entered.iter() {
+        for entity in entered {
+            let (Ok((_, component, _)), Some(since)) = (
+                components.get(*entity),
+                visibility.visible_since(*conn_id, *entity),
+            ) else {
+                continue;
+            };
+            let Some(bytes) = encode(&*component) else {
+                continue;
+            };
+            sent.forget(*conn_id, *entity);
+            send_component(net, sent, *conn_id, *entity, since, &*component, &bytes);
+        }
+    }
+
+    for (entity, component, _) in components.iter() {
+        if !component.is_changed() {
+            continue;
+        }
+        let Some(bytes) = encode(&*component) else {
+            continue;
+        };
+        for (conn_id, since) in visibility.viewers_since(entity) {
+            // Entities that just became visible were sent above
+            if visibility
+                .entered
+                .get(&conn_id)
+                .is_some_and(|entered| entered.contains(&entity))
+            {
+                continue;
+            }
+            send_component(net, sent, conn_id, entity, since, &*component, &bytes);
+        }
+    }
+
+    for entity in removed.read() {
+        sent.forget_entity(entity);
+        if replicated.contains(entity) {
+            for (conn_id, since) in visibility.viewers_since(entity) {
+                let _ = net.send_remote(
+                    conn_id,
+                    ComponentReplication::<C> {
+                        entity: entity.to_bits(),
+                        since,
+                        update: ComponentUpdate::Removed,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// The encoding deltas of the component are computed on
+fn encode<C: NetworkMessage>(component: &C) -> Option<Vec<u8>> {
+    match bincode::serialize(component) {
+        Ok(bytes) => Some(bytes),
+        Err(err) => {
+            error!("Could not serialize {}: {}", C::NAME, err);
+            None
+        }
+    }
+}
+
+/// Sends the component whole, or only what changed since it was last sent to the connection
+fn send_component<C, NP: NetworkProvider>(
+    net: &Network<NP>,
+    sent: &mut SentComponents,
+    conn_id: ConnectionId,
+    entity: Entity,
+    since: u32,
+    component: &C,
+    bytes: &[u8],
+) where
+    C: NetworkMessage + Clone,
+{
+    if net.is_local(conn_id) {
+        return;
+    }
+
+    // Deltas rely on every earlier update having arrived
+    let encoded = if NP::RELIABLE {
+        sent.encode(conn_id, entity, bytes)
+    } else {
+        Encoded::Full
+    };
+    let update = match encoded {
+        Encoded::Unchanged => return,
+        Encoded::Full => ComponentUpdate::Full(component.clone()),
+        Encoded::Delta(delta) => ComponentUpdate::Delta(delta),
+    };
+    let _ = net.send_message(
+        conn_id,
+        ComponentReplication {
+            entity: entity.to_bits(),
+            since,
+            update,
+        },
+    );
+}
+
+fn send_despawns<NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut removed: RemovedComponents<Replicated>,
+    visibility: Option<Res<ClientVisibility<NP>>>,
+) {
+    if !net.is_listening() {
+        removed.clear();
+        return;
+    }
+
+    if let Some(visibility) = visibility {
+        // Despawned entities stop being visible to everyone
+        removed.clear();
+        for (conn_id, left) in visibility.left.iter() {
+            for (entity, since) in left {
+                let _ = net.send_remote(
+                    *conn_id,
+                    EntityDespawned {
+                        entity: entity.to_bits(),
+                        since: *since,
+                    },
+                );
+            }
+        }
+        return;
+    }
+
+    for entity in removed.read() {
+        net.broadcast_remote(EntityDespawned {
+            entity: entity.to_bits(),
+            since: 0,
+        });
+    }
+}
+
+fn send_resource_updates<R, NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut network_events: NetworkEventReader<NP>,
+    resource: Option<Res<R>>,
+    mut existed: Local<bool>,
+) where
+    R: Resource + NetworkMessage + Clone,
+{
+    if !net.is_listening() {
+        network_events.clear();
+        return;
+    }
+
+    for event in network_events.read() {
+        if let (NetworkEvent::Connected(conn_id), Some(resource)) = (event, &resource) {
+            let _ = net.send_remote(
+                *conn_id,
+                ResourceReplication {
+                    resource: Some(R::clone(resource)),
+                },
+            );
+        }
+    }
+
+    match &resource {
+        Some(resource) if resource.is_changed() => net.broadcast_remote(ResourceReplication {
+            resource: Some(R::clone(resource)),
+        }),
+        None if *existed => net.broadcast_remote(ResourceReplication::<R> { resource: None }),
+        _ => (),
+    }
+    *existed = resource.is_some();
+}
+
+fn apply_resource_updates<R, NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut updates: NetworkDataReader<ResourceReplication<R>, NP>,
+    mut commands: Commands,
+) where
+    R: Resource + NetworkMessage + Clone,
+{
+    if net.is_listening() {
+        updates.clear();
+        return;
+    }
+
+    // Only the latest state matters
+    if let Some(update) = updates.read().last() {
+        match &update.resource {
+            Some(resource) => commands.insert_resource(resource.clone()),
+            None => commands.remove_resource::<R>(),
+        }
+    }
+}
+
+fn apply_component_updates<C, NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut updates: NetworkDataReader<ComponentReplication<C>, NP>,
+    mut entities: ResMut<ReplicatedEntities<NP>>,
+    mut commands: Commands,
+    // The encoding last received for each server entity, deltas apply to it
+    mut received: Local<HashMap<Entity, Vec<u8>>>,
+) where
+    C: Component + NetworkMessage + Clone,
+{
+    if net.is_listening() {
+        updates.clear();
+        received.clear();
+        return;
+    }
+
+    for update in updates.read() {
+        let Ok(server_entity) = Entity::try_from_bits(update.entity) else {
+            continue;
+        };
+        let Some(entity) =
+            entities.get_or_spawn(*update.source(), server_entity, update.since, &mut commands)
+        else {
+            continue;
+        };
+
+        match &update.update {
+            ComponentUpdate::Full(component) => {
+                if let Some(bytes) = encode(component) {
+                    received.insert(server_entity, bytes);
+                }
+                commands.entity(entity).insert(component.clone());
+            }
+            ComponentUpdate::Delta(delta) => {
+                let component = received
+                    .get(&server_entity)
+                    .and_then(|old| delta.apply(old))
+                    .and_then(|bytes| {
+                        let component = bincode::deserialize::<C>(&bytes).ok()?;
+                        Some((component, bytes))
+                    });
+                match component {
+                    Some((component, bytes)) => {
+                        received.insert(server_entity, bytes);
+                        commands.entity(entity).insert(component);
+                    }
+                    None => warn!(
+                        "Could not apply an update of {} to {:?}, it doesn't match the state last received",
+                        C::NAME,
+                        server_entity
+                    ),
+                }
+            }
+            ComponentUpdate::Removed => {
+                received.remove(&server_entity);
+                commands.entity(entity).remove::<C>();
+            }
+        }
+    }
+
+    // Forget the state of despawned mirrors
+    received.retain(|server_entity, _| entities.map.contains_key(server_entity));
+}
+
+fn apply_despawns<NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut despawns: NetworkDataReader<EntityDespawned, NP>,
+    mut network_events: NetworkEventReader<NP>,
+    mut entities: ResMut<ReplicatedEntities<NP>>,
+    replicas: Query<(Entity, &Replica<NP>)>,
+    mut commands: Commands,
+) {
+    entities.tombstones.retain(|_, (age, _)| {
+        *age += 1;
+        *age < DESPAWN_TOMBSTONE_FRAMES
+    });
+
+    if net.is_listening() {
+        despawns.clear();
+        network_events.clear();
+        return;
+    }
+
+    for despawn in despawns.read() {
+        let Ok(server_entity) = Entity::try_from_bits(despawn.entity) else {
+            continue;
+        };
+        // A newer mirror means the entity became visible again after it was despawned
+        if let Some((entity, since)) = entities.map.get(&server_entity).copied() {
+            if since <= despawn.since {
+                entities.map.remove(&server_entity);
+                commands.entity(entity).despawn();
+            }
+        }
+        let tombstone = entities
+            .tombstones
+            .entry(server_entity)
+            .or_insert((0, despawn.since));
+        *tombstone = (0, tombstone.1.max(despawn.since));
+    }
+
+    for event in network_events.read() {
+        if let NetworkEvent::Disconnected(conn_id) = event {
+            for (entity, replica) in replicas.iter() {
+                if replica.source == *conn_id {
+                    entities.map.remove(&replica.server_entity);
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Delta encoding of replicated components
+//!
+//! The server remembers the encoding of the component it last sent to each connection, and only sends the bytes
+//! that changed since, if that is smaller than the whole component. Clients apply them to the encoding they last received.
+
+use std::collections::HashMap;
+
+use bevy::prelude::Entity;
+use serde::{Deserialize, Serialize};
+
+use crate::ConnectionId;
+
+/// Unchanged bytes between two changed ones are sent along, if they are fewer than this
+const MERGE_GAP: usize = 8;
+
+/// The bytes an encoded run takes on top of its content, its offset and length
+const RUN_OVERHEAD: usize = 12;
+
+/// The changes turning an encoded component into a newer encoding of it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ByteDelta {
+    len: u32,
+    /// The changed bytes, with the offset they start at
+    runs: Vec<(u32, Vec<u8>)>,
+}
+
+impl ByteDelta {
+    pub(crate) fn between(old: &[u8], new: &[u8]) -> Self {
+        let mut runs: Vec<(u32, Vec<u8>)> = Vec::new();
+        for (index, byte) in new.iter().enumerate() {
+            if old.get(index) == Some(byte) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((start, run)) if index - (*start as usize + run.len()) <= MERGE_GAP => {
+                    let end = *start as usize + run.len();
+                    run.extend_from_slice(&new[end..=index]);
+                }
+                _ => runs.push((index as u32, vec![*byte])),
+            }
+        }
+        Self {
+            len: new.len() as u32,
+            runs,
+        }
+    }
+
+    /// The size of the delta once encoded, about
+    pub(crate) fn encoded_len(&self) -> usize {
+        RUN_OVERHEAD
+            + self
+                .runs
+                .iter()
+                .map(|(_, run)| RUN_OVERHEAD + run.len())
+                .sum::<usize>()
+    }
+
+    /// Returns [`None`] if the delta doesn't fit the encoding
+    pub(crate) fn apply(&self, old: &[u8]) -> Option<Vec<u8>> {
+        let mut bytes = old.to_vec();
+        bytes.resize(self.len as usize, 0);
+        for (start, run) in &self.runs {
+            let start = *start as usize;
+            bytes
+                .get_mut(start..start.checked_add(run.len())?)?
+                .copy_from_slice(run);
+        }
+        Some(bytes)
+    }
+}
+
+/// What is sent for a changed component
+pub(crate) enum Encoded {
+    /// The connection already has this state
+    Unchanged,
+    Full,
+    Delta(ByteDelta),
+}
+
+/// The encoding of a component last sent to each connection, per entity
+#[derive(Default)]
+pub(crate) struct SentComponents {
+    sent: HashMap<(ConnectionId, Entity), Vec<u8>>,
+}
+
+impl SentComponents {
+    /// Decides how to send the encoded component to the connection, and remembers it as sent
+    pub(crate) fn encode(
+        &mut self,
+        conn_id: ConnectionId,
+        entity: Entity,
+        bytes: &[u8],
+    ) -> Encoded {
+        let encoded = match self.sent.get(&(conn_id, entity)) {
+            Some(sent) if sent == bytes => return Encoded::Unchanged,
+            Some(sent) => {
+                let delta = ByteDelta::between(sent, bytes);
+                if delta.encoded_len() < bytes.len() {
+                    Encoded::Delta(delta)
+                } else {
+                    Encoded::Full
+                }
+            }
+            None => Encoded::Full,
+        };
+        self.sent.insert((conn_id, entity), bytes.to_vec());
+        encoded
+    }
+
+    /// The next update of the component to the connection is sent whole
+    pub(crate) fn forget(&mut self, conn_id: ConnectionId, entity: Entity) {
+        self.sent.remove(&(conn_id, entity));
+    }
+
+    /// The next update of the component is sent whole to every connection
+    pub(crate) fn forget_entity(&mut self, entity: Entity) {
+        self.sent
+            .retain(|(_, sent_entity), _| *sent_entity != entity);
+    }
+
+    pub(crate) fn forget_connection(&mut self, conn_id: ConnectionId) {
+        self.sent
+            .retain(|(sent_conn_id, _), _| *sent_conn_id != conn_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let old = vec![
+            1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+        ];
+        for new in [
+            old.clone(),
+            vec![
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 21,
+            ],
+            vec![
+                0, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 0,
+            ],
+            vec![1, 2, 3],
+            vec![
+                1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+            ],
+            Vec::new(),
+        ] {
+            let delta = ByteDelta::between(&old, &new);
+            assert_eq!(delta.apply(&old), Some(new));
+        }
+    }
+
+    #[test]
+    fn merges_close_changes() {
+        let old = [0; 32];
+        let mut new = [0; 32];
+        new[2] = 1;
+        new[6] = 1;
+        new[30] = 1;
+        let delta = ByteDelta::between(&old, &new);
+        assert_eq!(delta.runs, vec![(2, vec![1, 0, 0, 0, 1]), (30, vec![1])]);
+        assert!(delta.encoded_len() < new.len() + RUN_OVERHEAD * 3);
+    }
+
+    #[test]
+    fn rejects_runs_out_of_bounds() {
+        let delta = ByteDelta {
+            len: 4,
+            runs: vec![(2, vec![1, 2, 3])],
+        };
+        assert_eq!(delta.apply(&[0; 4]), None);
+    }
+
+    #[test]
+    fn sends_deltas_per_connection() {
+        let entity = Entity::from_raw(1);
+        let first = ConnectionId { id: 0 };
+        let second = ConnectionId { id: 1 };
+        let mut sent = SentComponents::default();
+        let old = [0; 64];
+        let mut new = [0; 64];
+        new[10] = 1;
+
+        assert!(matches!(sent.encode(first, entity, &old), Encoded::Full));
+        assert!(matches!(
+            sent.encode(first, entity, &old),
+            Encoded::Unchanged
+        ));
+        assert!(matches!(
+            sent.encode(first, entity, &new),
+            Encoded::Delta(_)
+        ));
+        assert!(matches!(sent.encode(second, entity, &new), Encoded::Full));
+
+        sent.forget_entity(entity);
+        assert!(matches!(sent.encode(first, entity, &new), Encoded::Full));
+    }
+}
@@ -0,0 +1,655 @@
+//! # Visibility
+//!
+//! By default every [`Replicated`] entity is sent to every client.
+//! Once a rule is added with [`AppNetworkVisibility::add_visibility_rule`], each connection only sees the entities all rules allow,
+//! and replication only sends an entity to the connections that can see it.
+//!
+//! A rule compares a component on the connection's entity with the same component on a replicated entity,
+//! so [`EventworkPlugin::with_connection_entities`](crate::EventworkPlugin::with_connection_entities) has to be enabled.
+//! - Replicated entities without the component are visible to everyone.
+//! - Connections whose entity doesn't have the component only see replicated entities without it.
+//!
+//! When an entity becomes visible to a connection its replicated components are sent to it,
+//! when it stops being visible it is despawned on that client.
+//! The computed visibility is available as the [`ClientVisibility`] resource, which can also send other messages
+//! to the viewers of an entity with [`ClientVisibility::broadcast_visible`].
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{tcp::TcpProvider, replication::visibility::AppNetworkVisibility};
+//!
+//! #[derive(Component, PartialEq)]
+//! struct Team(u8);
+//!
+//! #[derive(Component)]
+//! struct Position(Vec2);
+//!
+//! fn build(app: &mut App) {
+//!     // Only see entities of the same team
+//!     app.add_visibility_rule::<Team, TcpProvider>(|viewer, target| viewer == target);
+//!     // And only those closer than 100 units
+//!     app.add_visibility_rule::<Position, TcpProvider>(|viewer, target| {
+//!         viewer.0.distance(target.0) < 100.0
+//!     });
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use bevy::prelude::{
+    Added, App, Changed, Component, Entity, IntoSystemConfigs, Query, RemovedComponents, Res,
+    ResMut, Resource, With,
+};
+
+use crate::{
+    managers::{Network, NetworkProvider},
+    ConnectionId, EventworkSchedules, EventworkSet, NetworkMessage,
+};
+
+use super::Replicated;
+
+/// The replicated entities each connection of a [`Network`] can see, kept up to date every frame
+///
+/// The rules are only evaluated again for new connections and entities, and for those whose components changed.
+/// Only exists once a rule was added with [`AppNetworkVisibility::add_visibility_rule`].
+#[derive(Resource)]
+pub struct ClientVisibility<NP: NetworkProvider> {
+    tick: u32,
+    /// The tick each visible entity became visible at, per connection
+    visible: HashMap<ConnectionId, HashMap<Entity, u32>>,
+    /// The entities each rule hides from each connection
+    hidden: Vec<HashMap<ConnectionId, HashSet<Entity>>>,
+    /// The connections and entities that appeared this frame, every rule evaluates them
+    new_viewers: Vec<ConnectionId>,
+    new_targets: Vec<Entity>,
+    /// The pairs whose visibility may have changed this frame
+    dirty: HashSet<(ConnectionId, Entity)>,
+    pub(crate) entered: HashMap<ConnectionId, Vec<Entity>>,
+    pub(crate) left: HashMap<ConnectionId, Vec<(Entity, u32)>>,
+    marker: PhantomData<NP>,
+}
+
+impl<NP: NetworkProvider> Default for ClientVisibility<NP> {
+    fn default() -> Self {
+        Self {
+            tick: 0,
+            visible: HashMap::new(),
+            hidden: Vec::new(),
+            new_viewers: Vec::new(),
+            new_targets: Vec::new(),
+            dirty: HashSet::new(),
+            entered: HashMap::new(),
+            left: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<NP: NetworkProvider> ClientVisibility<NP> {
+    /// Returns true if the connection can see the entity
+    pub fn is_visible(&self, conn_id: ConnectionId, entity: Entity) -> bool {
+        self.visible
+            .get(&conn_id)
+            .is_some_and(|visible| visible.contains_key(&entity))
+    }
+
+    /// The entities the connection can see
+    pub fn visible_entities(&self, conn_id: ConnectionId) -> impl Iterator<Item = Entity> + '_ {
+        self.visible
+            .get(&conn_id)
+            .into_iter()
+            .flat_map(|visible| visible.keys().copied())
+    }
+
+    /// The connections that can see the entity
+    pub fn viewers(&self, entity: Entity) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.viewers_since(entity).map(|(conn_id, _)| conn_id)
+    }
+
+    /// Send a message to all connections that can see the entity
+    ///
+    /// The message is only serialized once, no matter how many clients it is sent to.
+    pub fn broadcast_visible<T: NetworkMessage + Clone>(
+        &self,
+        net: &Network<NP>,
+        entity: Entity,
+        message: T,
+    ) {
+        net.broadcast_filtered(message, |conn_id| self.is_visible(*conn_id, entity));
+    }
+
+    /// The connections that can see the entity, with the tick it became visible to them at
+    pub(crate) fn viewers_since(
+        &self,
+        entity: Entity,
+    ) -> impl Iterator<Item = (ConnectionId, u32)> + '_ {
+        self.visible.iter().filter_map(move |(conn_id, visible)| {
+            visible.get(&entity).map(|since| (*conn_id, *since))
+        })
+    }
+
+    /// The tick the entity became visible to the connection at
+    pub(crate) fn visible_since(&self, conn_id: ConnectionId, entity: Entity) -> Option<u32> {
+        self.visible
+            .get(&conn_id)
+            .and_then(|visible| visible.get(&entity))
+            .copied()
+    }
+}
+
+/// A utility trait on [`App`] to restrict which clients replicated entities are sent to
+pub trait AppNetworkVisibility {
+    /// Add a rule deciding which replicated entities the connections of the given provider can see
+    ///
+    /// The rule is called with the component of the connection's entity and the component of the replicated entity.
+    /// An entity is only visible if all rules allow it.
+    fn add_visibility_rule<C: Component, NP: NetworkProvider>(
+        &mut self,
+        rule: impl Fn(&C, &C) -> bool + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl AppNetworkVisibility for App {
+    fn add_visibility_rule<C: Component, NP: NetworkProvider>(
+        &mut self,
+        rule: impl Fn(&C, &C) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        let schedules = EventworkSchedules::of::<NP>(self);
+        if !self.world().contains_resource::<ClientVisibility<NP>>() {
+            self.init_resource::<ClientVisibility<NP>>();
+            self.add_systems(
+                schedules.send,
+                (begin_visibility::<NP>, finish_visibility::<NP>)
+                    .chain()
+                    .in_set(EventworkSet::Send),
+            );
+        }
+
+        let rule_index = {
+            let mut visibility = self.world_mut().resource_mut::<ClientVisibility<NP>>();
+            visibility.hidden.push(HashMap::new());
+            visibility.hidden.len() - 1
+        };
+
+        self.add_systems(
+            schedules.send,
+            (move |net: Res<Network<NP>>,
+                   mut visibility: ResMut<ClientVisibility<NP>>,
+                   components: Query<&C>,
+                   changed: Query<Entity, Changed<C>>,
+                   mut removed: RemovedComponents<C>,
+                   replicated: Query<Entity, With<Replicated>>| {
+                let visibility = &mut *visibility;
+                let mut viewers: HashSet<ConnectionId> =
+                    visibility.new_viewers.iter().copied().collect();
+                let mut targets: HashSet<Entity> = visibility.new_targets.iter().copied().collect();
+                for entity in changed.iter().chain(removed.read()) {
+                    if let Some(conn_id) = net
+                        .connection_of_entity(entity)
+                        .filter(|conn_id| visibility.visible.contains_key(conn_id))
+                    {
+                        viewers.insert(conn_id);
+                    }
+                    if replicated.contains(entity) {
+                        targets.insert(entity);
+                    }
+                }
+
+                let hidden = &mut visibility.hidden[rule_index];
+                let dirty = &mut visibility.dirty;
+                let mut evaluate = |conn_id: ConnectionId, viewer: Option<&C>, entity: Entity| {
+                    let hides = components
+                        .get(entity)
+                        .is_ok_and(|target| !viewer.is_some_and(|viewer| rule(viewer, target)));
+                    let hidden = hidden.entry(conn_id).or_default();
+                    let flipped = if hides {
+                        hidden.insert(entity)
+                    } else {
+                        hidden.remove(&entity)
+                    };
+                    if flipped {
+                        dirty.insert((conn_id, entity));
+                    }
+                };
+                let viewer_of = |conn_id: ConnectionId| {
+                    net.connection_entity(conn_id)
+                        .and_then(|entity| components.get(entity).ok())
+                };
+
+                for conn_id in viewers.iter().copied() {
+                    let viewer = viewer_of(conn_id);
+                    for entity in replicated.iter() {
+                        evaluate(conn_id, viewer, entity);
+                    }
+                }
+                for conn_id in visibility.visible.keys().copied() {
+                    // Already evaluated for all entities above
+                    if viewers.contains(&conn_id) {
+                        continue;
+                    }
+                    let viewer = viewer_of(conn_id);
+                    for entity in targets.iter().copied() {
+                        evaluate(conn_id, viewer, entity);
+                    }
+                }
+            })
+            .after(begin_visibility::<NP>)
+            .before(finish_visibility::<NP>)
+            .in_set(EventworkSet::Send),
+        )
+    }
+}
+
+/// Tracks the connections and replicated entities coming and going, and marks the pairs to decide on again
+fn begin_visibility<NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    mut visibility: ResMut<ClientVisibility<NP>>,
+    added: Query<Entity, Added<Replicated>>,
+    mut removed: RemovedComponents<Replicated>,
+    replicated: Query<Entity, With<Replicated>>,
+) {
+    let visibility = &mut *visibility;
+    visibility.tick = visibility.tick.wrapping_add(1);
+    visibility.new_viewers.clear();
+    visibility.new_targets.clear();
+
+    let connections: HashSet<ConnectionId> = if net.is_listening() {
+        net.connection_ids().collect()
+    } else {
+        HashSet::new()
+    };
+
+    // Clients that are gone don't need to be told about anything
+    visibility
+        .visible
+        .retain(|conn_id, _| connections.contains(conn_id));
+    for hidden in visibility.hidden.iter_mut() {
+        hidden.retain(|conn_id, _| connections.contains(conn_id));
+    }
+
+    for conn_id in connections {
+        if visibility.visible.contains_key(&conn_id) {
+            continue;
+        }
+        visibility.visible.insert(conn_id, HashMap::new());
+        visibility.new_viewers.push(conn_id);
+        visibility
+            .dirty
+            .extend(replicated.iter().map(|entity| (conn_id, entity)));
+    }
+
+    for entity in removed.read() {
+        if replicated.contains(entity) {
+            // Replicated again right away
+            continue;
+        }
+        for hidden in visibility
+            .hidden
+            .iter_mut()
+            .flat_map(|rule| rule.values_mut())
+        {
+            hidden.remove(&entity);
+        }
+        for (conn_id, visible) in visibility.visible.iter() {
+            if visible.contains_key(&entity) {
+                visibility.dirty.insert((*conn_id, entity));
+            }
+        }
+    }
+
+    for entity in added.iter() {
+        visibility.new_targets.push(entity);
+        visibility
+            .dirty
+            .extend(visibility.visible.keys().map(|conn_id| (*conn_id, entity)));
+    }
+}
+
+/// Applies the decisions of the rules to the pairs marked by [`begin_visibility`], and collects the entities that entered and left
+pub(crate) fn finish_visibility<NP: NetworkProvider>(
+    mut visibility: ResMut<ClientVisibility<NP>>,
+    replicated: Query<(), With<Replicated>>,
+) {
+    let visibility = &mut *visibility;
+    visibility.entered.clear();
+    visibility.left.clear();
+
+    for (conn_id, entity) in visibility.dirty.drain() {
+        let Some(visible) = visibility.visible.get_mut(&conn_id) else {
+            // The connection went away
+            continue;
+        };
+        let shown = replicated.contains(entity)
+            && !visibility.hidden.iter().any(|rule| {
+                rule.get(&conn_id)
+                    .is_some_and(|hidden| hidden.contains(&entity))
+            });
+        match (shown, visible.get(&entity).copied()) {
+            (true, None) => {
+                visible.insert(entity, visibility.tick);
+                visibility.entered.entry(conn_id).or_default().push(entity);
+            }
+            (false, Some(since)) => {
+                visible.remove(&entity);
+                visibility
+                    .left
+                    .entry(conn_id)
+                    .or_default()
+                    .push((entity, since));
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{SocketAddr, TcpListener},
+        time::Duration,
+    };
+
+    use bevy::prelude::{Mut, World};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{
+        replication::{AppNetworkReplication, Replica},
+        tcp::{NetworkSettings, TcpProvider},
+        EventworkPlugin, EventworkRuntime, SimulatedRuntime,
+    };
+
+    #[derive(Component, Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Team(u8);
+
+    impl NetworkMessage for Team {
+        const NAME: &'static str = "test:Team";
+    }
+
+    /// A server only showing entities of the same team, and its clients
+    struct Game {
+        runtime: SimulatedRuntime,
+        server: App,
+        addr: SocketAddr,
+        clients: Vec<App>,
+    }
+
+    impl Game {
+        fn new() -> Self {
+            let runtime = SimulatedRuntime::new();
+            let mut server = App::new();
+            server.add_plugins(
+                EventworkPlugin::<TcpProvider, SimulatedRuntime>::default()
+                    .with_connection_entities(),
+            );
+            server.insert_resource(NetworkSettings::default());
+            server.insert_resource(EventworkRuntime(runtime.clone()));
+            server.replicate::<Team, TcpProvider>();
+            server.add_visibility_rule::<Team, TcpProvider>(|viewer, target| viewer == target);
+
+            // A free port, for the server to listen on
+            let addr = TcpListener::bind("127.0.0.1:0")
+                .and_then(|listener| listener.local_addr())
+                .expect("No free port");
+            server
+                .world_mut()
+                .resource_scope(|world, mut net: Mut<Network<TcpProvider>>| {
+                    net.listen(addr, &runtime, world.resource::<NetworkSettings>())
+                })
+                .expect("Failed to listen");
+            // Binds the listener
+            runtime.run_until_stalled();
+
+            Self {
+                runtime,
+                server,
+                addr,
+                clients: Vec::new(),
+            }
+        }
+
+        /// Connects a new client, and puts its connection entity on the server in the team
+        fn connect(&mut self, team: u8) -> ConnectionId {
+            let mut client = App::new();
+            client.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+            client.insert_resource(NetworkSettings::default());
+            client.insert_resource(EventworkRuntime(self.runtime.clone()));
+            client.replicate::<Team, TcpProvider>();
+            client.world().resource::<Network<TcpProvider>>().connect(
+                self.addr,
+                &self.runtime,
+                &NetworkSettings::default(),
+            );
+            self.clients.push(client);
+
+            let known: Vec<ConnectionId> = self.net().connection_ids().collect();
+            let mut conn_id = None;
+            self.update_until(|game| {
+                conn_id = game
+                    .net()
+                    .connection_ids()
+                    .find(|conn_id| !known.contains(conn_id));
+                conn_id.is_some()
+            });
+            let conn_id = conn_id.expect("The client didn't connect");
+            let entity = self
+                .net()
+                .connection_entity(conn_id)
+                .expect("No connection entity");
+            self.server
+                .world_mut()
+                .entity_mut(entity)
+                .insert(Team(team));
+            conn_id
+        }
+
+        fn net(&self) -> &Network<TcpProvider> {
+            self.server.world().resource::<Network<TcpProvider>>()
+        }
+
+        fn visibility(&self) -> &ClientVisibility<TcpProvider> {
+            self.server
+                .world()
+                .resource::<ClientVisibility<TcpProvider>>()
+        }
+
+        fn update(&mut self) {
+            self.runtime.run_until_stalled();
+            self.server.update();
+            for client in self.clients.iter_mut() {
+                client.update();
+            }
+        }
+
+        fn update_until(&mut self, mut done: impl FnMut(&mut Self) -> bool) {
+            for _ in 0..200 {
+                self.update();
+                if done(self) {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            panic!("Timed out");
+        }
+
+        /// The local entity and team mirroring the server entity on the client
+        fn mirror(
+            &mut self,
+            client: usize,
+            server_entity: Entity,
+        ) -> Option<(Entity, Option<Team>)> {
+            mirrors(self.clients[client].world_mut())
+                .into_iter()
+                .find(|(_, mirrored, _)| *mirrored == server_entity)
+                .map(|(entity, _, team)| (entity, team))
+        }
+
+        /// Waits until the client mirrors the server entity with the team, or doesn't mirror it at all
+        fn expect_mirror(&mut self, client: usize, server_entity: Entity, team: Option<Team>) {
+            self.update_until(|game| {
+                let mirror = game.mirror(client, server_entity);
+                match &team {
+                    Some(team) => {
+                        mirror.is_some_and(|(_, mirrored)| mirrored.as_ref() == Some(team))
+                    }
+                    None => mirror.is_none(),
+                }
+            });
+        }
+
+        fn set_team(&mut self, entity: Entity, team: u8) {
+            self.server
+                .world_mut()
+                .entity_mut(entity)
+                .insert(Team(team));
+        }
+    }
+
+    fn mirrors(world: &mut World) -> Vec<(Entity, Entity, Option<Team>)> {
+        world
+            .query::<(Entity, &Replica<TcpProvider>, Option<&Team>)>()
+            .iter(world)
+            .map(|(entity, replica, team)| (entity, replica.server_entity(), team.cloned()))
+            .collect()
+    }
+
+    #[test]
+    fn entities_enter_when_a_rule_allows_them() {
+        let mut game = Game::new();
+        let conn_id = game.connect(1);
+        let red = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        let blue = game.server.world_mut().spawn((Replicated, Team(2))).id();
+        let neutral = game.server.world_mut().spawn(Replicated).id();
+
+        game.expect_mirror(0, red, Some(Team(1)));
+        assert!(game.mirror(0, blue).is_none());
+        // Visible, though there is nothing to mirror without a replicated component
+        assert!(game.visibility().is_visible(conn_id, red));
+        assert!(game.visibility().is_visible(conn_id, neutral));
+        assert!(!game.visibility().is_visible(conn_id, blue));
+
+        // The target joins the team of the viewer
+        game.set_team(blue, 1);
+        game.expect_mirror(0, blue, Some(Team(1)));
+        assert!(game.visibility().is_visible(conn_id, blue));
+    }
+
+    #[test]
+    fn entities_leave_when_the_viewer_or_target_changes() {
+        let mut game = Game::new();
+        let conn_id = game.connect(1);
+        let viewer = game
+            .net()
+            .connection_entity(conn_id)
+            .expect("No connection entity");
+        let first = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        let second = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        game.expect_mirror(0, first, Some(Team(1)));
+        game.expect_mirror(0, second, Some(Team(1)));
+
+        // The target changes team
+        game.set_team(first, 2);
+        game.expect_mirror(0, first, None);
+        assert!(game.mirror(0, second).is_some());
+        assert!(!game.visibility().is_visible(conn_id, first));
+
+        // The viewer changes team, and sees the first one again instead
+        game.set_team(viewer, 2);
+        game.expect_mirror(0, second, None);
+        game.expect_mirror(0, first, Some(Team(2)));
+        assert!(game.visibility().is_visible(conn_id, first));
+        assert!(!game.visibility().is_visible(conn_id, second));
+    }
+
+    #[test]
+    fn reentering_ignores_the_stale_despawn() {
+        let mut game = Game::new();
+        let conn_id = game.connect(1);
+        let entity = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        game.expect_mirror(0, entity, Some(Team(1)));
+        let (mirror, _) = game.mirror(0, entity).expect("Not mirrored");
+        let since = game
+            .visibility()
+            .visible_since(conn_id, entity)
+            .expect("Not visible");
+
+        // Leaves and enters again before the client reads either
+        game.set_team(entity, 2);
+        game.server.update();
+        game.set_team(entity, 1);
+        game.server.update();
+        let reentered = game
+            .visibility()
+            .visible_since(conn_id, entity)
+            .expect("Not visible");
+        assert!(reentered > since);
+
+        // Both arrive in the same frame, the despawn is older than the mirror it would apply to
+        for _ in 0..10 {
+            game.runtime.run_until_stalled();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        game.clients[0].update();
+        let (remirrored, team) = game.mirror(0, entity).expect("The stale despawn applied");
+        assert_ne!(remirrored, mirror);
+        assert_eq!(team, Some(Team(1)));
+
+        for _ in 0..10 {
+            game.update();
+        }
+        assert_eq!(game.mirror(0, entity), Some((remirrored, Some(Team(1)))));
+    }
+
+    #[test]
+    fn replicating_again_in_the_same_frame_keeps_the_entity() {
+        let mut game = Game::new();
+        let conn_id = game.connect(1);
+        let entity = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        game.expect_mirror(0, entity, Some(Team(1)));
+        let (mirror, _) = game.mirror(0, entity).expect("Not mirrored");
+
+        game.server
+            .world_mut()
+            .entity_mut(entity)
+            .remove::<Replicated>()
+            .insert(Replicated);
+        for _ in 0..10 {
+            game.update();
+        }
+        assert!(game.visibility().is_visible(conn_id, entity));
+        assert_eq!(game.mirror(0, entity), Some((mirror, Some(Team(1)))));
+
+        // And it is still replicated
+        game.server
+            .world_mut()
+            .entity_mut(entity)
+            .remove::<Replicated>()
+            .insert((Replicated, Team(2)));
+        game.expect_mirror(0, entity, None);
+        game.set_team(entity, 1);
+        game.expect_mirror(0, entity, Some(Team(1)));
+    }
+
+    #[test]
+    fn new_connections_see_existing_entities() {
+        let mut game = Game::new();
+        let red = game.server.world_mut().spawn((Replicated, Team(1))).id();
+        let blue = game.server.world_mut().spawn((Replicated, Team(2))).id();
+        game.update();
+
+        let first = game.connect(1);
+        game.expect_mirror(0, red, Some(Team(1)));
+        let second = game.connect(2);
+        game.expect_mirror(1, blue, Some(Team(2)));
+
+        assert!(game.mirror(0, blue).is_none());
+        assert!(game.mirror(1, red).is_none());
+        assert!(game.visibility().is_visible(first, red));
+        assert!(game.visibility().is_visible(second, blue));
+    }
+}
@@ -0,0 +1,202 @@
+//! # Rooms
+//!
+//! Rooms group connections of a [`Network`], so a message can be sent to everyone in a room at once.
+//!
+//! A room can be any hashable type, a [`String`] for named rooms or your own type for typed ones.
+//! Register it with [`AppNetworkRooms::add_rooms`], then use the [`Rooms`] resource to
+//! [`join`](Rooms::join) and [`leave`](Rooms::leave) rooms and to [`broadcast_to_room`](Rooms::broadcast_to_room).
+//! Connections leave all rooms automatically once they disconnect.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{
+//!     Network, NetworkEvent, NetworkMessage,
+//!     tcp::TcpProvider,
+//!     rooms::{AppNetworkRooms, Rooms},
+//! };
+//! use serde::{Serialize, Deserialize};
+//!
+//! #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+//! struct Lobby(u32);
+//!
+//! #[derive(Serialize, Deserialize, Clone)]
+//! struct ChatMessage(String);
+//!
+//! impl NetworkMessage for ChatMessage {
+//!     const NAME: &'static str = "example:ChatMessage";
+//! }
+//!
+//! fn build(app: &mut App) {
+//!     app.add_rooms::<Lobby, TcpProvider>();
+//! }
+//!
+//! fn join_lobby(
+//!     mut network_events: EventReader<NetworkEvent>,
+//!     mut rooms: ResMut<Rooms<Lobby, TcpProvider>>,
+//! ) {
+//!     for event in network_events.read() {
+//!         if let NetworkEvent::Connected(conn_id) = event {
+//!             rooms.join(Lobby(3), *conn_id);
+//!         }
+//!     }
+//! }
+//!
+//! fn greet_lobby(net: Res<Network<TcpProvider>>, rooms: Res<Rooms<Lobby, TcpProvider>>) {
+//!     rooms.broadcast_to_room(&net, &Lobby(3), ChatMessage("Welcome to lobby 3!".to_string()));
+//! }
+//! ```
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    hash::Hash,
+    marker::PhantomData,
+};
+
+use bevy::prelude::{debug, App, IntoSystemConfigs, ResMut, Resource};
+
+use crate::{
+    labelled::NetworkEventReader,
+    managers::{Network, NetworkProvider},
+    ConnectionId, EventworkSchedules, EventworkSet, NetworkEvent, NetworkMessage,
+};
+
+/// Anything that can identify a room
+pub trait Room: Debug + Clone + Eq + Hash + Send + Sync + 'static {}
+
+impl<T: Debug + Clone + Eq + Hash + Send + Sync + 'static> Room for T {}
+
+/// The members of all rooms of type `R` for a [`Network`]
+#[derive(Resource)]
+pub struct Rooms<R: Room, NP: NetworkProvider> {
+    members: HashMap<R, HashSet<ConnectionId>>,
+    joined: HashMap<ConnectionId, HashSet<R>>,
+    marker: PhantomData<NP>,
+}
+
+impl<R: Room, NP: NetworkProvider> Default for Rooms<R, NP> {
+    fn default() -> Self {
+        Self {
+            members: HashMap::new(),
+            joined: HashMap::new(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<R: Room, NP: NetworkProvider> Rooms<R, NP> {
+    /// Add a connection to a room, returns false if it already was a member
+    pub fn join(&mut self, room: R, conn_id: ConnectionId) -> bool {
+        self.joined.entry(conn_id).or_default().insert(room.clone());
+        self.members.entry(room).or_default().insert(conn_id)
+    }
+
+    /// Remove a connection from a room, returns false if it wasn't a member
+    pub fn leave(&mut self, room: &R, conn_id: ConnectionId) -> bool {
+        if let Some(joined) = self.joined.get_mut(&conn_id) {
+            joined.remove(room);
+            if joined.is_empty() {
+                self.joined.remove(&conn_id);
+            }
+        }
+
+        let Some(members) = self.members.get_mut(room) else {
+            return false;
+        };
+        let removed = members.remove(&conn_id);
+        if members.is_empty() {
+            self.members.remove(room);
+        }
+        removed
+    }
+
+    /// Remove a connection from all rooms it joined
+    pub fn leave_all(&mut self, conn_id: ConnectionId) {
+        for room in self.joined.remove(&conn_id).into_iter().flatten() {
+            if let Some(members) = self.members.get_mut(&room) {
+                members.remove(&conn_id);
+                if members.is_empty() {
+                    self.members.remove(&room);
+                }
+            }
+        }
+    }
+
+    /// Returns true if the connection is a member of the room
+    pub fn contains(&self, room: &R, conn_id: ConnectionId) -> bool {
+        self.members
+            .get(room)
+            .is_some_and(|members| members.contains(&conn_id))
+    }
+
+    /// The connections in a room
+    pub fn members(&self, room: &R) -> impl Iterator<Item = ConnectionId> + '_ {
+        self.members.get(room).into_iter().flatten().copied()
+    }
+
+    /// The rooms a connection joined
+    pub fn rooms_of(&self, conn_id: ConnectionId) -> impl Iterator<Item = &R> + '_ {
+        self.joined.get(&conn_id).into_iter().flatten()
+    }
+
+    /// All rooms with at least one member
+    pub fn rooms(&self) -> impl Iterator<Item = &R> + '_ {
+        self.members.keys()
+    }
+
+    /// Send a message to every connection in a room
+    ///
+    /// The message is only serialized once, no matter how many clients it is sent to.
+    pub fn broadcast_to_room<T: NetworkMessage + Clone>(
+        &self,
+        net: &Network<NP>,
+        room: &R,
+        message: T,
+    ) {
+        let Some(members) = self.members.get(room) else {
+            return;
+        };
+        net.broadcast_filtered(message, |conn_id| members.contains(conn_id));
+    }
+}
+
+/// A utility trait on [`App`] to group connections into rooms
+pub trait AppNetworkRooms {
+    /// Register a type of room for the connections of the given provider
+    ///
+    /// ## Details
+    /// This will:
+    /// - Add the [`Rooms<R, NP>`] resource
+    /// - Remove connections from all rooms once they disconnect
+    fn add_rooms<R: Room, NP: NetworkProvider>(&mut self) -> &mut Self;
+}
+
+impl AppNetworkRooms for App {
+    fn add_rooms<R: Room, NP: NetworkProvider>(&mut self) -> &mut Self {
+        if self.world().contains_resource::<Rooms<R, NP>>() {
+            return self;
+        }
+
+        debug!("Registered a new room type: {}", std::any::type_name::<R>());
+
+        let schedules = EventworkSchedules::of::<NP>(self);
+        self.init_resource::<Rooms<R, NP>>();
+        self.add_systems(
+            schedules.receive,
+            leave_rooms_on_disconnect::<R, NP>.in_set(EventworkSet::Receive),
+        )
+    }
+}
+
+fn leave_rooms_on_disconnect<R: Room, NP: NetworkProvider>(
+    mut network_events: NetworkEventReader<NP>,
+    mut rooms: ResMut<Rooms<R, NP>>,
+) {
+    for event in network_events.read() {
+        if let NetworkEvent::Disconnected(conn_id) = event {
+            rooms.leave_all(*conn_id);
+        }
+    }
+}
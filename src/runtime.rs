@@ -1,15 +1,33 @@
 mod bevy_runtime;
+mod clock;
+mod simulated_runtime;
+#[cfg(not(target_arch = "wasm32"))]
+mod threaded_runtime;
+#[cfg(feature = "tokio")]
+mod tokio_runtime;
 
 use std::future::Future;
 
 use bevy::prelude::{Deref, DerefMut, Resource};
 
+pub(crate) use clock::update_clock;
+pub use clock::Clock;
+pub use simulated_runtime::{SimulatedClock, SimulatedRuntime, SimulatedSleep};
+#[cfg(not(target_arch = "wasm32"))]
+pub use threaded_runtime::{ThreadedRuntime, ThreadedRuntimeBuilder};
+
 /// A Resource that provides access to the runtime to internal Eventwork systems.
 ///
 /// This *must* be inserted into the app for the Eventwork plugin to work
 #[derive(Resource, DerefMut, Deref)]
 pub struct EventworkRuntime<RT: Runtime + Send + Sync>(pub RT);
 
+impl<RT: Runtime + Default> Default for EventworkRuntime<RT> {
+    fn default() -> Self {
+        Self(RT::default())
+    }
+}
+
 /// A runtime abstraction allowing you to use any runtime for spicy
 pub trait Runtime: Send + Sync + 'static {
     /// Associated handle
@@ -20,6 +38,11 @@ pub trait Runtime: Send + Sync + 'static {
 
     /// Create a long running background task that is *not* [`Send`] and [`Sync`] and will be run on the main thread
     fn spawn_local(&self, task: impl Future<Output = ()> + 'static) -> Self::JoinHandle;
+
+    /// The clock timeouts and delays are measured with, the real time unless the runtime has a clock of its own
+    fn clock(&self) -> Clock {
+        Clock::real()
+    }
 }
 
 /// A runtime abstraction allowing you to use any runtime with spicy
@@ -43,3 +66,10 @@ where
 {
     runtime.spawn_local(future)
 }
+
+/// The tasks of the [`SimulatedRuntime`] and the [`ThreadedRuntime`]
+impl JoinHandle for Option<async_task::Task<()>> {
+    fn abort(&mut self) {
+        self.take();
+    }
+}
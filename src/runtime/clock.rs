@@ -0,0 +1,138 @@
+use std::{sync::OnceLock, time::Duration};
+
+use bevy::{
+    prelude::{DetectChanges, Res, ResMut, Resource},
+    utils::Instant,
+};
+
+use super::{EventworkRuntime, Runtime, SimulatedClock};
+
+/// The clock eventwork measures its timeouts and delays with, the time of the [`Runtime`].
+///
+/// This is the real time, except for a [`SimulatedRuntime`](super::SimulatedRuntime), whose [`SimulatedClock`]
+/// only moves when a test advances it, so timeouts happen exactly when the test expects them to.
+/// The [`EventworkPlugin`](crate::EventworkPlugin) keeps the clock of the [`EventworkRuntime`] as a resource.
+///
+/// The clock is a cheap handle, clones share the same time.
+#[derive(Resource, Clone, Default)]
+pub struct Clock {
+    simulated: Option<SimulatedClock>,
+}
+
+impl Clock {
+    /// The real time
+    pub fn real() -> Self {
+        Self::default()
+    }
+
+    /// The time passed since the start of the clock
+    ///
+    /// The real time starts with the first call, simulated time when its runtime was created.
+    pub fn now(&self) -> Duration {
+        match &self.simulated {
+            Some(clock) => clock.now(),
+            None => real_start().elapsed(),
+        }
+    }
+
+    /// Wait until the clock moved forward by `duration`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn sleep(&self, duration: Duration) {
+        self.sleep_until(self.now() + duration).await
+    }
+
+    /// Wait until the clock reaches `deadline`, see [`Clock::now`]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn sleep_until(&self, deadline: Duration) {
+        match &self.simulated {
+            Some(clock) => clock.sleep_until(deadline).await,
+            None => {
+                async_io::Timer::at(real_start() + deadline).await;
+            }
+        }
+    }
+}
+
+impl From<SimulatedClock> for Clock {
+    fn from(clock: SimulatedClock) -> Self {
+        Self {
+            simulated: Some(clock),
+        }
+    }
+}
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.simulated {
+            Some(_) => write!(f, "Clock [Simulated {:?}]", self.now()),
+            None => write!(f, "Clock [Real]"),
+        }
+    }
+}
+
+/// The point the real time of all clocks is measured from
+fn real_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// Keeps the [`Clock`] resource in line with the runtime, for runtimes inserted or replaced after the plugin was added
+pub(crate) fn update_clock<RT: Runtime>(
+    runtime: Option<Res<EventworkRuntime<RT>>>,
+    mut clock: ResMut<Clock>,
+) {
+    if let Some(runtime) = runtime.filter(|runtime| runtime.is_changed()) {
+        *clock = runtime.clock();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use bevy::prelude::App;
+
+    use super::*;
+    use crate::{
+        tcp::{NetworkSettings, TcpProvider},
+        EventworkPlugin, SimulatedRuntime,
+    };
+
+    #[test]
+    fn follows_the_simulated_runtime() {
+        let runtime = SimulatedRuntime::new();
+        let mut app = App::new();
+        app.add_plugins(EventworkPlugin::<TcpProvider, SimulatedRuntime>::default());
+        app.insert_resource(NetworkSettings::default());
+        app.insert_resource(EventworkRuntime(runtime.clone()));
+        app.update();
+
+        let clock = app.world().resource::<Clock>().clone();
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let flag = timed_out.clone();
+        let _task = runtime.spawn(async move {
+            clock.sleep(Duration::from_secs(3)).await;
+            flag.store(true, Ordering::Relaxed);
+        });
+
+        runtime.advance(Duration::from_secs(2));
+        assert!(!timed_out.load(Ordering::Relaxed));
+        runtime.advance(Duration::from_secs(1));
+        assert!(timed_out.load(Ordering::Relaxed));
+        assert_eq!(
+            app.world().resource::<Clock>().now(),
+            Duration::from_secs(3)
+        );
+    }
+
+    #[test]
+    fn real_time_passes() {
+        let clock = Clock::real();
+        let start = clock.now();
+        futures_lite::future::block_on(clock.sleep(Duration::from_millis(10)));
+        assert!(clock.now() >= start + Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use async_task::{Runnable, Task};
+
+use crate::{Clock, Runtime};
+
+/// A [`Runtime`] for tests, running tasks one after another on the thread that steps it.
+///
+/// Nothing runs until the test calls [`SimulatedRuntime::run_until_stalled`] or [`SimulatedRuntime::advance`],
+/// and tasks always run in the order they were woken in, so a test runs the same way every time,
+/// as long as its tasks don't wait on real I/O.
+/// Its [`Clock`](Runtime::clock) is a virtual [`SimulatedClock`], which only moves forward when the test advances it,
+/// so everything eventwork waits for, like timeouts, happens at the same point of the test every time.
+///
+/// The runtime is a cheap handle, clones step the same tasks.
+///
+/// ```rust
+/// use std::{sync::{Arc, atomic::{AtomicBool, Ordering}}, time::Duration};
+/// use bevy_eventwork::{Runtime, SimulatedRuntime};
+///
+/// let runtime = SimulatedRuntime::new();
+/// let timed_out = Arc::new(AtomicBool::new(false));
+///
+/// let clock = runtime.clock();
+/// let flag = timed_out.clone();
+/// let _task = runtime.spawn(async move {
+///     clock.sleep(Duration::from_secs(3)).await;
+///     flag.store(true, Ordering::Relaxed);
+/// });
+///
+/// runtime.advance(Duration::from_secs(2));
+/// assert!(!timed_out.load(Ordering::Relaxed));
+/// runtime.advance(Duration::from_secs(1));
+/// assert!(timed_out.load(Ordering::Relaxed));
+/// ```
+#[derive(Clone, Default)]
+pub struct SimulatedRuntime {
+    queue: Arc<Mutex<VecDeque<Runnable>>>,
+    clock: SimulatedClock,
+}
+
+impl SimulatedRuntime {
+    /// A runtime without tasks, with its clock at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run tasks until all of them are waiting, returns how many times a task was run
+    pub fn run_until_stalled(&self) -> usize {
+        let mut runs = 0;
+        loop {
+            // Release the lock before running, so tasks can wake each other
+            let Some(runnable) = lock(&self.queue).pop_front() else {
+                return runs;
+            };
+            runnable.run();
+            runs += 1;
+        }
+    }
+
+    /// Move the clock forward, waking the tasks sleeping until then in order, and run tasks until all of them are waiting
+    pub fn advance(&self, by: Duration) {
+        let target = self.clock.now() + by;
+        self.run_until_stalled();
+        // Step from deadline to deadline, so timers set by woken tasks fire at the right time too
+        while let Some(deadline) = self.clock.next_deadline().filter(|next| *next <= target) {
+            self.clock.set(deadline);
+            self.run_until_stalled();
+        }
+        self.clock.set(target);
+        self.run_until_stalled();
+    }
+
+    /// Returns true if no task is waiting to be run
+    pub fn is_stalled(&self) -> bool {
+        lock(&self.queue).is_empty()
+    }
+
+    fn schedule(&self) -> impl Fn(Runnable) + Send + Sync + 'static {
+        let queue = self.queue.clone();
+        move |runnable| lock(&queue).push_back(runnable)
+    }
+}
+
+impl Runtime for SimulatedRuntime {
+    type JoinHandle = Option<Task<()>>;
+
+    fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) -> Self::JoinHandle {
+        let (runnable, task) = async_task::spawn(task, self.schedule());
+        runnable.schedule();
+        Some(task)
+    }
+
+    /// The task has to be run on the thread it was spawned on.
+    fn spawn_local(&self, task: impl Future<Output = ()> + 'static) -> Self::JoinHandle {
+        let (runnable, task) = async_task::spawn_local(task, self.schedule());
+        runnable.schedule();
+        Some(task)
+    }
+
+    /// The virtual clock of this runtime
+    fn clock(&self) -> Clock {
+        Clock::from(self.clock.clone())
+    }
+}
+
+/// A virtual clock that only moves when its [`SimulatedRuntime`] is advanced
+///
+/// The clock is a cheap handle, clones share the same time.
+#[derive(Clone, Default)]
+pub struct SimulatedClock {
+    inner: Arc<Mutex<ClockState>>,
+}
+
+#[derive(Default)]
+struct ClockState {
+    now: Duration,
+    next_timer: u64,
+    timers: BTreeMap<(Duration, u64), Waker>,
+}
+
+impl SimulatedClock {
+    /// The time passed since the runtime was created
+    pub fn now(&self) -> Duration {
+        lock(&self.inner).now
+    }
+
+    /// Wait until the clock was advanced by `duration`
+    pub fn sleep(&self, duration: Duration) -> SimulatedSleep {
+        self.sleep_until(self.now() + duration)
+    }
+
+    /// Wait until the clock reaches `deadline`
+    pub fn sleep_until(&self, deadline: Duration) -> SimulatedSleep {
+        SimulatedSleep {
+            clock: self.clone(),
+            deadline,
+            timer: None,
+        }
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        lock(&self.inner)
+            .timers
+            .keys()
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+
+    fn set(&self, now: Duration) {
+        let wakers = {
+            let mut state = lock(&self.inner);
+            state.now = now;
+            let later = state.timers.split_off(&(now, u64::MAX));
+            std::mem::replace(&mut state.timers, later)
+        };
+        for waker in wakers.into_values() {
+            waker.wake();
+        }
+    }
+}
+
+/// A future waiting on a [`SimulatedClock`], see [`SimulatedClock::sleep`]
+pub struct SimulatedSleep {
+    clock: SimulatedClock,
+    deadline: Duration,
+    timer: Option<u64>,
+}
+
+impl Future for SimulatedSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = lock(&self.clock.inner);
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let timer = match self.timer {
+            Some(timer) => timer,
+            None => {
+                let timer = state.next_timer;
+                state.next_timer += 1;
+                timer
+            }
+        };
+        state
+            .timers
+            .insert((self.deadline, timer), cx.waker().clone());
+        drop(state);
+        self.timer = Some(timer);
+        Poll::Pending
+    }
+}
+
+impl Drop for SimulatedSleep {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer {
+            lock(&self.clock.inner)
+                .timers
+                .remove(&(self.deadline, timer));
+        }
+    }
+}
+
+/// The locks are never held while running a task, so a panicking task can't poison them
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,141 @@
+use std::{
+    future::Future,
+    sync::Arc,
+    thread::{self, JoinHandle as ThreadHandle},
+};
+
+use async_channel::Sender;
+use async_executor::{Executor, Task};
+use bevy::log::error;
+
+use crate::Runtime;
+
+/// A [`Runtime`] running eventwork's tasks on threads of its own, so networking isn't slowed down
+/// by busy frames of Bevy's task pools.
+///
+/// The threads are stopped once the runtime is dropped, for example by removing the [`EventworkRuntime`](crate::EventworkRuntime) resource.
+///
+/// ```rust
+/// use bevy::prelude::*;
+/// use bevy_eventwork::{EventworkPlugin, EventworkRuntime, ThreadedRuntime, tcp::TcpProvider};
+///
+/// let mut app = App::new();
+/// app.add_plugins(EventworkPlugin::<TcpProvider, ThreadedRuntime>::default());
+/// app.insert_resource(EventworkRuntime(ThreadedRuntime::builder().num_threads(2).build()));
+/// ```
+pub struct ThreadedRuntime {
+    executor: Arc<Executor<'static>>,
+    shutdown: Option<Sender<()>>,
+    threads: Vec<ThreadHandle<()>>,
+}
+
+impl ThreadedRuntime {
+    /// Configure the threads of a new runtime
+    pub fn builder() -> ThreadedRuntimeBuilder {
+        ThreadedRuntimeBuilder::default()
+    }
+}
+
+impl Default for ThreadedRuntime {
+    /// A runtime with a single thread
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+impl Drop for ThreadedRuntime {
+    fn drop(&mut self) {
+        // Closing the channel lets every thread return
+        self.shutdown.take();
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                error!("A thread of the eventwork runtime panicked");
+            }
+        }
+    }
+}
+
+/// Configures the threads of a [`ThreadedRuntime`]
+#[derive(Debug, Clone)]
+pub struct ThreadedRuntimeBuilder {
+    num_threads: usize,
+    thread_name: String,
+    stack_size: Option<usize>,
+}
+
+impl Default for ThreadedRuntimeBuilder {
+    fn default() -> Self {
+        Self {
+            num_threads: 1,
+            thread_name: String::from("Eventwork"),
+            stack_size: None,
+        }
+    }
+}
+
+impl ThreadedRuntimeBuilder {
+    /// The amount of threads to run tasks on, at least one
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads.max(1);
+        self
+    }
+
+    /// The name of the threads, numbered if there is more than one
+    pub fn thread_name(mut self, thread_name: impl Into<String>) -> Self {
+        self.thread_name = thread_name.into();
+        self
+    }
+
+    /// The stack size of the threads, in bytes
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = Some(stack_size);
+        self
+    }
+
+    /// Start the threads
+    pub fn build(self) -> ThreadedRuntime {
+        let executor = Arc::new(Executor::new());
+        let (shutdown, shutdown_rx) = async_channel::bounded::<()>(1);
+
+        let threads = (0..self.num_threads)
+            .map(|index| {
+                let executor = executor.clone();
+                let shutdown_rx = shutdown_rx.clone();
+                let name = if self.num_threads == 1 {
+                    self.thread_name.clone()
+                } else {
+                    format!("{} ({})", self.thread_name, index)
+                };
+
+                let mut builder = thread::Builder::new().name(name);
+                if let Some(stack_size) = self.stack_size {
+                    builder = builder.stack_size(stack_size);
+                }
+                builder
+                    .spawn(move || {
+                        let _ = futures_lite::future::block_on(executor.run(shutdown_rx.recv()));
+                    })
+                    .expect("Could not spawn eventwork runtime thread")
+            })
+            .collect();
+
+        ThreadedRuntime {
+            executor,
+            shutdown: Some(shutdown),
+            threads,
+        }
+    }
+}
+
+impl Runtime for ThreadedRuntime {
+    type JoinHandle = Option<Task<()>>;
+
+    fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) -> Self::JoinHandle {
+        Some(self.executor.spawn(task))
+    }
+
+    /// The tasks run on other threads, so they have to be [`Send`]. Eventwork only spawns local tasks on wasm, which has no threads.
+    fn spawn_local(&self, _task: impl Future<Output = ()> + 'static) -> Self::JoinHandle {
+        panic!("ThreadedRuntime can't run tasks that aren't Send");
+    }
+}
@@ -0,0 +1,29 @@
+use crate::Runtime;
+
+use super::JoinHandle;
+
+impl Runtime for tokio::runtime::Handle {
+    type JoinHandle = tokio::task::JoinHandle<()>;
+
+    fn spawn(
+        &self,
+        task: impl std::future::Future<Output = ()> + Send + 'static,
+    ) -> Self::JoinHandle {
+        self.spawn(task)
+    }
+
+    /// Tokio can only run tasks that aren't [`Send`] on a [`tokio::task::LocalSet`],
+    /// so this panics when not called from within one.
+    fn spawn_local(
+        &self,
+        task: impl std::future::Future<Output = ()> + 'static,
+    ) -> Self::JoinHandle {
+        tokio::task::spawn_local(task)
+    }
+}
+
+impl JoinHandle for tokio::task::JoinHandle<()> {
+    fn abort(&mut self) {
+        tokio::task::JoinHandle::abort(self);
+    }
+}
@@ -0,0 +1,83 @@
+//! # Connection state
+//!
+//! Run conditions to only run systems while a [`Network`] is connected or listening:
+//!
+//! ```rust
+//! use bevy::prelude::*;
+//! use bevy_eventwork::{tcp::TcpProvider, state::{is_connected, is_listening}};
+//!
+//! fn build(app: &mut App) {
+//!     app.add_systems(Update, send_inputs.run_if(is_connected::<TcpProvider>()));
+//!     app.add_systems(Update, simulate_world.run_if(is_listening::<TcpProvider>()));
+//! }
+//! # fn send_inputs() {}
+//! # fn simulate_world() {}
+//! ```
+//!
+//! With the `state` feature, `EventworkPlugin::with_connection_state` additionally drives the
+//! `ClientConnectionState` Bevy state from the network of a client,
+//! so `OnEnter(ClientConnectionState::Connected)` and `in_state` can be used.
+
+#[cfg(feature = "state")]
+use std::sync::atomic::Ordering;
+
+use bevy::prelude::Res;
+#[cfg(feature = "state")]
+use bevy::prelude::{NextState, ResMut, State, States};
+
+use crate::managers::{Network, NetworkProvider};
+
+/// A run condition that is true while the [`Network`] has at least one established connection
+pub fn is_connected<NP: NetworkProvider>() -> impl FnMut(Option<Res<Network<NP>>>) -> bool + Clone {
+    |net| net.is_some_and(|net| net.has_connections())
+}
+
+/// A run condition that is true while the [`Network`] is listening for new connections
+pub fn is_listening<NP: NetworkProvider>() -> impl FnMut(Option<Res<Network<NP>>>) -> bool + Clone {
+    |net| net.is_some_and(|net| net.is_listening())
+}
+
+#[cfg(feature = "state")]
+/// The state of a client's connection to its server
+///
+/// Enabled with [`EventworkPlugin::with_connection_state`](crate::EventworkPlugin::with_connection_state),
+/// only changes while the network isn't listening.
+#[derive(States, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClientConnectionState {
+    /// Not connected, and no connection attempt is in flight
+    #[default]
+    Disconnected,
+    /// [`Network::connect`] was called and the connection isn't established yet
+    Connecting,
+    /// A connection is established
+    Connected,
+    /// Connecting again, after an established connection was lost without calling [`Network::disconnect`]
+    Reconnecting,
+}
+
+#[cfg(feature = "state")]
+pub(crate) fn update_connection_state<NP: NetworkProvider>(
+    net: Res<Network<NP>>,
+    state: Res<State<ClientConnectionState>>,
+    mut next_state: ResMut<NextState<ClientConnectionState>>,
+) {
+    if net.is_listening() {
+        return;
+    }
+
+    let new_state = if net.has_connections() {
+        ClientConnectionState::Connected
+    } else if net.is_connecting() {
+        if net.connection_lost.load(Ordering::Relaxed) {
+            ClientConnectionState::Reconnecting
+        } else {
+            ClientConnectionState::Connecting
+        }
+    } else {
+        ClientConnectionState::Disconnected
+    };
+
+    if *state.get() != new_state {
+        next_state.set(new_state);
+    }
+}
@@ -4,15 +4,16 @@ use crate::{
     async_channel::{Receiver, Sender},
     async_trait,
     error::NetworkError,
+    framing,
     managers::NetworkProvider,
     NetworkPacket,
 };
 use async_net::{TcpListener, TcpStream};
 use bevy::{
-    log::{debug, error, info, trace},
+    log::{debug, info},
     prelude::Resource,
 };
-use futures_lite::{AsyncReadExt, AsyncWriteExt, FutureExt, Stream};
+use futures_lite::{FutureExt, Stream};
 use std::future::Future;
 
 #[derive(Default, Debug)]
@@ -67,122 +68,28 @@ impl NetworkProvider for TcpProvider {
     }
 
     async fn recv_loop(
-        mut read_half: Self::ReadHalf,
+        read_half: Self::ReadHalf,
         messages: Sender<NetworkPacket>,
         settings: Self::NetworkSettings,
     ) {
-        let mut buffer = vec![0; settings.max_packet_length];
-        loop {
-            info!("Reading message length");
-            let length = match read_half.read(&mut buffer[..8]).await {
-                Ok(0) => {
-                    // EOF, meaning the TCP stream has closed.
-                    info!("Client disconnected");
-                    // TODO: probably want to do more than just quit the receive task.
-                    //       to let eventwork know that the peer disconnected.
-                    break;
-                }
-                Ok(8) => {
-                    let bytes = &buffer[..8];
-                    u64::from_le_bytes(
-                        bytes
-                            .try_into()
-                            .expect("Couldn't read bytes from connection!"),
-                    ) as usize
-                }
-                Ok(n) => {
-                    error!(
-                        "Could not read enough bytes for header. Expected 8, got {}",
-                        n
-                    );
-                    break;
-                }
-                Err(err) => {
-                    error!("Encountered error while fetching length: {}", err);
-                    break;
-                }
-            };
-            info!("Message length: {}", length);
-
-            if length > settings.max_packet_length {
-                error!(
-                    "Received too large packet: {} > {}",
-                    length, settings.max_packet_length
-                );
-                break;
-            }
-
-            info!("Reading message into buffer");
-            match read_half.read_exact(&mut buffer[..length]).await {
-                Ok(()) => (),
-                Err(err) => {
-                    error!(
-                        "Encountered error while fetching stream of length {}: {}",
-                        length, err
-                    );
-                    break;
-                }
-            }
-            info!("Message read");
-
-            let packet: NetworkPacket = match bincode::deserialize(&buffer[..length]) {
-                Ok(packet) => packet,
-                Err(err) => {
-                    error!("Failed to decode network packet from: {}", err);
-                    break;
-                }
-            };
-
-            if messages.send(packet).await.is_err() {
-                error!("Failed to send decoded message to eventwork");
-                break;
-            }
-            info!("Message deserialized and sent to eventwork");
-        }
+        framing::recv_packets(read_half, messages, settings.max_packet_length).await
     }
 
     async fn send_loop(
-        mut write_half: Self::WriteHalf,
+        write_half: Self::WriteHalf,
         messages: Receiver<NetworkPacket>,
         _settings: Self::NetworkSettings,
     ) {
-        while let Ok(message) = messages.recv().await {
-            let encoded = match bincode::serialize(&message) {
-                Ok(encoded) => encoded,
-                Err(err) => {
-                    error!("Could not encode packet {:?}: {}", message, err);
-                    continue;
-                }
-            };
-
-            let len = encoded.len() as u64;
-            debug!("Sending a new message of size: {}", len);
-
-            match write_half.write(&len.to_le_bytes()).await {
-                Ok(_) => (),
-                Err(err) => {
-                    error!("Could not send packet length: {:?}: {}", len, err);
-                    break;
-                }
-            }
-
-            trace!("Sending the content of the message!");
-
-            match write_half.write_all(&encoded).await {
-                Ok(_) => (),
-                Err(err) => {
-                    error!("Could not send packet: {:?}: {}", message, err);
-                    break;
-                }
-            }
-
-            trace!("Succesfully written all!");
-        }
+        framing::send_packets(write_half, messages).await
     }
 
     fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf) {
         (combined.clone(), combined)
     }
+
+    fn peer_addr(socket: &Self::Socket) -> Option<SocketAddr> {
+        socket.peer_addr().ok()
+    }
 }
 
 #[derive(Clone, Debug, Resource)]
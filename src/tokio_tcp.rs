@@ -0,0 +1,187 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+use crate::{
+    async_channel::{Receiver, Sender},
+    async_trait,
+    error::NetworkError,
+    framing,
+    managers::NetworkProvider,
+    NetworkPacket,
+};
+use bevy::{
+    log::{debug, error, info},
+    prelude::Resource,
+};
+use futures_lite::{AsyncRead, AsyncWrite, Stream};
+use tokio::{
+    io::ReadBuf,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+};
+
+#[derive(Default, Debug)]
+/// Provides a tcp stream and listener for eventwork, using tokio's reactor.
+///
+/// Use it together with the tokio [`Runtime`](crate::Runtime), sockets of this provider only work on a tokio runtime.
+///
+/// ```rust,no_run
+/// use bevy::prelude::*;
+/// use bevy_eventwork::{EventworkPlugin, EventworkRuntime, tokio_tcp::{NetworkSettings, TokioTcpProvider}};
+///
+/// let runtime = tokio::runtime::Builder::new_multi_thread()
+///     .enable_io()
+///     .build()
+///     .expect("Could not build tokio runtime");
+///
+/// let mut app = App::new();
+/// app.add_plugins(EventworkPlugin::<TokioTcpProvider, tokio::runtime::Handle>::default());
+/// app.insert_resource(EventworkRuntime(runtime.handle().clone()));
+/// app.insert_resource(NetworkSettings::default());
+/// ```
+pub struct TokioTcpProvider;
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl NetworkProvider for TokioTcpProvider {
+    type NetworkSettings = NetworkSettings;
+
+    type Socket = TcpStream;
+
+    type ReadHalf = OwnedReadHalf;
+
+    type WriteHalf = OwnedWriteHalf;
+
+    type ConnectInfo = SocketAddr;
+
+    type AcceptInfo = SocketAddr;
+
+    type AcceptStream = OwnedIncoming;
+
+    async fn accept_loop(
+        accept_info: Self::AcceptInfo,
+        _: Self::NetworkSettings,
+    ) -> Result<Self::AcceptStream, NetworkError> {
+        let listener = TcpListener::bind(accept_info)
+            .await
+            .map_err(NetworkError::Listen)?;
+
+        Ok(OwnedIncoming { inner: listener })
+    }
+
+    async fn connect_task(
+        connect_info: Self::ConnectInfo,
+        _: Self::NetworkSettings,
+    ) -> Result<Self::Socket, NetworkError> {
+        info!("Beginning connection");
+        let stream = TcpStream::connect(connect_info)
+            .await
+            .map_err(NetworkError::Connection)?;
+
+        info!("Connected!");
+        debug!("Connected to: {:?}", stream.peer_addr());
+        Ok(stream)
+    }
+
+    async fn recv_loop(
+        read_half: Self::ReadHalf,
+        messages: Sender<NetworkPacket>,
+        settings: Self::NetworkSettings,
+    ) {
+        framing::recv_packets(Compat(read_half), messages, settings.max_packet_length).await
+    }
+
+    async fn send_loop(
+        write_half: Self::WriteHalf,
+        messages: Receiver<NetworkPacket>,
+        _settings: Self::NetworkSettings,
+    ) {
+        framing::send_packets(Compat(write_half), messages).await
+    }
+
+    fn split(combined: Self::Socket) -> (Self::ReadHalf, Self::WriteHalf) {
+        combined.into_split()
+    }
+
+    fn peer_addr(socket: &Self::Socket) -> Option<SocketAddr> {
+        socket.peer_addr().ok()
+    }
+}
+
+#[derive(Clone, Debug, Resource)]
+#[allow(missing_copy_implementations)]
+/// Settings to configure the network, both client and server
+pub struct NetworkSettings {
+    /// Maximum packet size in bytes. If a client ever exceeds this size, they will be disconnected
+    ///
+    /// ## Default
+    /// The default is set to 10MiB
+    pub max_packet_length: usize,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            max_packet_length: 10 * 1024 * 1024,
+        }
+    }
+}
+
+/// A stream of the connections accepted by a tokio [`TcpListener`]
+pub struct OwnedIncoming {
+    inner: TcpListener,
+}
+
+impl Stream for OwnedIncoming {
+    type Item = TcpStream;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.inner.poll_accept(cx) {
+            Poll::Ready(Ok((stream, _))) => Poll::Ready(Some(stream)),
+            Poll::Ready(Err(err)) => {
+                error!("Could not accept connection: {}", err);
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Lets the framing shared with the other tcp provider use tokio's socket halves
+struct Compat<T>(T);
+
+impl<T: tokio::io::AsyncRead + Unpin> AsyncRead for Compat<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(Pin::new(&mut self.0).poll_read(cx, &mut buf))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl<T: tokio::io::AsyncWrite + Unpin> AsyncWrite for Compat<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}